@@ -1,16 +1,17 @@
+use crate::raw_batch::RawBatch;
+use crate::timestamp::Timestamp;
 use crate::types::*;
 use crate::utils::*;
-use rocksdb::WriteBatch;
 
 pub struct Batch {
-    pub(in crate) inner: WriteBatch,
+    pub(in crate) inner: RawBatch,
     table_id: TableId,
 }
 
 impl Batch {
     #[inline]
     pub(in crate) fn new(table_id: TableId) -> Batch {
-        Batch { inner: WriteBatch::default(), table_id }
+        Batch { inner: RawBatch::default(), table_id }
     }
 
     #[inline]
@@ -26,15 +27,26 @@ impl Batch {
         self.inner.delete(build_inner_key(self.table_id, key))
     }
 
+    #[inline]
+    pub fn put_at<T, V>(&mut self, ts: T, value: V)
+    where
+        T: Timestamp,
+        V: AsRef<[u8]>, {
+        self.inner.put(build_inner_key(self.table_id, ts.to_key()), value)
+    }
+
+    #[inline]
+    pub fn delete_at<T: Timestamp>(&mut self, ts: T) {
+        self.inner.delete(build_inner_key(self.table_id, ts.to_key()))
+    }
+
     #[inline]
     pub fn delete_range<F, T>(&mut self, from_key: F, to_key: T)
     where
         F: AsRef<[u8]>,
         T: AsRef<[u8]>, {
-        self.inner.delete(build_delete_range_hint_table_inner_key(&from_key, &to_key));
-        self.inner.delete_range(
-            build_inner_key(self.table_id, from_key),
-            build_inner_key(self.table_id, to_key),
-        )
+        let from_key = build_inner_key(self.table_id, from_key);
+        let to_key = build_inner_key(self.table_id, to_key);
+        self.inner.delete_range(from_key, to_key)
     }
 }
@@ -1,15 +1,15 @@
+use crate::raw_batch::RawBatch;
 use crate::types::*;
 use crate::utils::*;
-use rocksdb::WriteBatch;
 
 pub struct BatchX {
-    pub(in crate) inner: WriteBatch,
+    pub(in crate) inner: RawBatch,
 }
 
 impl BatchX {
     #[inline]
     pub(in crate) fn new() -> BatchX {
-        BatchX { inner: WriteBatch::default() }
+        BatchX { inner: RawBatch::default() }
     }
 
     #[inline]
@@ -30,8 +30,8 @@ impl BatchX {
     where
         F: AsRef<[u8]>,
         T: AsRef<[u8]>, {
-        self.inner.delete(build_delete_range_hint_table_inner_key(&from_key, &to_key));
-        self.inner
-            .delete_range(build_inner_key(table_id, from_key), build_inner_key(table_id, to_key))
+        let from_key = build_inner_key(table_id, from_key);
+        let to_key = build_inner_key(table_id, to_key);
+        self.inner.delete_range(from_key, to_key)
     }
 }
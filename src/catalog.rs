@@ -0,0 +1,188 @@
+use crate::consts::*;
+use crate::memcomparable::prefix_end;
+use crate::types::*;
+use crate::utils::*;
+use crate::Error;
+use rocksdb::{DBRawIterator, ReadOptions, DB};
+
+/// Iterates over `(name, id)` pairs of tables in name order, optionally filtered by a glob.
+pub struct CatalogIterator<'a> {
+    inner: DBRawIterator<'a>,
+    pattern: Option<Vec<char>>,
+    started: bool,
+    done: bool,
+}
+
+impl<'a> CatalogIterator<'a> {
+    pub(in crate) fn new(db: &'a DB, prefix: &str, pattern: Option<&str>) -> Self {
+        let lower_bound = build_name_to_id_table_inner_key(prefix);
+        let upper_bound = prefix_end(&lower_bound).unwrap();
+        let mut opts = ReadOptions::default();
+        opts.set_total_order_seek(true);
+        opts.set_iterate_lower_bound(lower_bound.to_vec());
+        opts.set_iterate_upper_bound(upper_bound.to_vec());
+        CatalogIterator {
+            inner: db.raw_iterator_opt(opts),
+            pattern: pattern.map(|pattern| pattern.chars().collect()),
+            started: false,
+            done: false,
+        }
+    }
+
+    fn decode_entry(key: &[u8], value: &[u8]) -> Result<(String, TableId), Error> {
+        let name = extract_key(key);
+        let name = std::str::from_utf8(name)
+            .map_err(|e| Error::corruption(format!("Invalid table name {:?}: {}", name, e)))?;
+        if value.len() != TABLE_ID_LEN {
+            return Err(Error::corruption(format!("Invalid table id {:?} of {}", value, name)));
+        }
+        Ok((name.to_owned(), u8s_to_table_id(value)))
+    }
+}
+
+impl<'a> Iterator for CatalogIterator<'a> {
+    type Item = Result<(String, TableId), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            if self.started {
+                self.inner.next();
+            } else {
+                self.inner.seek_to_first();
+                self.started = true;
+            }
+            let (key, value) = match (self.inner.key(), self.inner.value()) {
+                (Some(key), Some(value)) => (key, value),
+                _ => {
+                    self.done = true;
+                    return self.inner.status().err().map(|e| Err(e.into()));
+                }
+            };
+            let entry = Self::decode_entry(key, value);
+            match (&entry, &self.pattern) {
+                (Ok((name, _)), Some(pattern)) if !glob_match(pattern, name) => continue,
+                (Err(_), _) => self.done = true,
+                _ => {}
+            }
+            return Some(entry);
+        }
+        None
+    }
+}
+
+/// Returns the part of a glob before its first wildcard.
+#[inline]
+pub(in crate) fn glob_literal_prefix(pattern: &str) -> &str {
+    let len = pattern.find(['*', '?']).unwrap_or(pattern.len());
+    &pattern[..len]
+}
+
+/// Matches a name against a glob where `*` matches any sequence of characters and `?` matches
+/// exactly one.
+fn glob_match(pattern: &[char], name: &str) -> bool {
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where to resume when the characters after the last `*` stop matching.
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    backtrack = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+fn collect_names(iter: CatalogIterator) -> Vec<String> {
+    iter.map(|entry| entry.unwrap().0).collect()
+}
+
+#[test]
+fn test_glob_match() {
+    let pattern: Vec<char> = "huobi.*.usdt.1min".chars().collect();
+    assert!(glob_match(&pattern, "huobi.btc.usdt.1min"));
+    assert!(glob_match(&pattern, "huobi.eth.btc.usdt.1min"));
+    assert!(!glob_match(&pattern, "huobi.btc.usdt.5min"));
+    assert!(!glob_match(&pattern, "binance.btc.usdt.1min"));
+    let pattern: Vec<char> = "huobi.???.usdt.*".chars().collect();
+    assert!(glob_match(&pattern, "huobi.btc.usdt.1min"));
+    assert!(!glob_match(&pattern, "huobi.doge.usdt.1min"));
+    assert_eq!(glob_literal_prefix("huobi.*.usdt.1min"), "huobi.");
+    assert_eq!(glob_literal_prefix("huobi"), "huobi");
+}
+
+#[test]
+fn test_tables() {
+    run_test("test_tables", |db| {
+        for name in &["huobi.eth.usdt.1min", "binance.btc.usdt.1min", "huobi.btc.usdt.1min"] {
+            db.new_table(name).unwrap();
+        }
+        let tables: Vec<(String, TableId)> = db.tables().map(Result::unwrap).collect();
+        assert_eq!(
+            tables,
+            vec![
+                ("binance.btc.usdt.1min".to_owned(), [0, 0, 4, 1]),
+                ("huobi.btc.usdt.1min".to_owned(), [0, 0, 4, 2]),
+                ("huobi.eth.usdt.1min".to_owned(), [0, 0, 4, 0]),
+            ]
+        );
+    })
+}
+
+#[test]
+fn test_tables_with_prefix() {
+    run_test("test_tables_with_prefix", |db| {
+        for name in &["huobi.btc.usdt.1min", "huobi.btc.usdt.5min", "huobi.eth.usdt.1min"] {
+            db.new_table(name).unwrap();
+        }
+        db.new_table("huobj").unwrap();
+        assert_eq!(
+            collect_names(db.tables_with_prefix("huobi.btc.")),
+            vec!["huobi.btc.usdt.1min", "huobi.btc.usdt.5min"]
+        );
+        assert_eq!(collect_names(db.tables_with_prefix("huobi")).len(), 3);
+        assert!(collect_names(db.tables_with_prefix("okex")).is_empty());
+    })
+}
+
+#[test]
+fn test_tables_matching() {
+    run_test("test_tables_matching", |db| {
+        for name in &["huobi.btc.usdt.1min", "huobi.btc.usdt.5min", "huobi.eth.usdt.1min"] {
+            db.new_table(name).unwrap();
+        }
+        db.new_table("binance.btc.usdt.1min").unwrap();
+        assert_eq!(
+            collect_names(db.tables_matching("huobi.*.usdt.1min")),
+            vec!["huobi.btc.usdt.1min", "huobi.eth.usdt.1min"]
+        );
+        assert_eq!(collect_names(db.tables_matching("*.btc.*")).len(), 3);
+    })
+}
+
+#[test]
+fn test_tables_with_corrupted_id() {
+    run_test("test_tables_with_corrupted_id", |db| {
+        db.new_table("huobi.btc.usdt.1min").unwrap();
+        db.inner.put(build_name_to_id_table_inner_key("huobi.btc.usdt.5min"), b"x").unwrap();
+        let mut tables = db.tables();
+        assert!(tables.next().unwrap().is_ok());
+        assert!(matches!(tables.next(), Some(Err(Error::Corruption(_)))));
+        assert!(tables.next().is_none());
+    })
+}
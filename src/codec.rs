@@ -0,0 +1,239 @@
+use crate::memcomparable::{decode, encode, Memcomparable};
+use crate::timestamp::*;
+use byteorder::{BigEndian, ByteOrder};
+use bytes::Bytes;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodecError {
+    message: String,
+}
+
+impl CodecError {
+    pub fn new<M: Into<String>>(message: M) -> Self {
+        CodecError { message: message.into() }
+    }
+}
+
+impl Display for CodecError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "Codec error: {}", self.message)
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+/// Encodes a key so that the byte-wise order of encoded keys matches the order of the keys.
+pub trait KeyCodec: Sized {
+    fn encode_key(&self) -> Bytes;
+
+    fn decode_key(key: &[u8]) -> Result<Self, CodecError>;
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// key codecs
+////////////////////////////////////////////////////////////////////////////////
+macro_rules! impl_key_codec_for_uint {
+    ($ty:ty, $len:expr, $write:path, $read:path) => {
+        impl KeyCodec for $ty {
+            #[inline]
+            fn encode_key(&self) -> Bytes {
+                let mut buf = [0; $len];
+                $write(&mut buf, *self);
+                Bytes::copy_from_slice(&buf)
+            }
+
+            #[inline]
+            fn decode_key(key: &[u8]) -> Result<Self, CodecError> {
+                if key.len() != $len {
+                    return Err(CodecError::new(format!(
+                        "Expected {} bytes for {}, got {}",
+                        $len,
+                        stringify!($ty),
+                        key.len()
+                    )));
+                }
+                Ok($read(key))
+            }
+        }
+    };
+}
+
+impl_key_codec_for_uint!(u16, 2, BigEndian::write_u16, BigEndian::read_u16);
+impl_key_codec_for_uint!(u32, 4, BigEndian::write_u32, BigEndian::read_u32);
+impl_key_codec_for_uint!(u64, 8, BigEndian::write_u64, BigEndian::read_u64);
+impl_key_codec_for_uint!(u128, 16, BigEndian::write_u128, BigEndian::read_u128);
+
+impl KeyCodec for u8 {
+    #[inline]
+    fn encode_key(&self) -> Bytes {
+        Bytes::copy_from_slice(&[*self])
+    }
+
+    #[inline]
+    fn decode_key(key: &[u8]) -> Result<Self, CodecError> {
+        match key {
+            [byte] => Ok(*byte),
+            _ => Err(CodecError::new(format!("Expected 1 byte for u8, got {}", key.len()))),
+        }
+    }
+}
+
+macro_rules! impl_key_codec_for_timestamp {
+    ($ty:ty) => {
+        impl KeyCodec for $ty {
+            #[inline]
+            fn encode_key(&self) -> Bytes {
+                Bytes::copy_from_slice(&self.to_key())
+            }
+
+            #[inline]
+            fn decode_key(key: &[u8]) -> Result<Self, CodecError> {
+                <$ty>::from_key(key).ok_or_else(|| {
+                    CodecError::new(format!(
+                        "Expected {} bytes for {}, got {}",
+                        TIMESTAMP_KEY_LEN,
+                        stringify!($ty),
+                        key.len()
+                    ))
+                })
+            }
+        }
+    };
+}
+
+impl_key_codec_for_timestamp!(Secs);
+impl_key_codec_for_timestamp!(Millis);
+impl_key_codec_for_timestamp!(Nanos);
+
+impl KeyCodec for String {
+    #[inline]
+    fn encode_key(&self) -> Bytes {
+        Bytes::copy_from_slice(self.as_bytes())
+    }
+
+    #[inline]
+    fn decode_key(key: &[u8]) -> Result<Self, CodecError> {
+        String::from_utf8(key.to_vec()).map_err(|e| CodecError::new(e.to_string()))
+    }
+}
+
+impl KeyCodec for Vec<u8> {
+    #[inline]
+    fn encode_key(&self) -> Bytes {
+        Bytes::copy_from_slice(self)
+    }
+
+    #[inline]
+    fn decode_key(key: &[u8]) -> Result<Self, CodecError> {
+        Ok(key.to_vec())
+    }
+}
+
+impl KeyCodec for Bytes {
+    #[inline]
+    fn encode_key(&self) -> Bytes {
+        self.clone()
+    }
+
+    #[inline]
+    fn decode_key(key: &[u8]) -> Result<Self, CodecError> {
+        Ok(Bytes::copy_from_slice(key))
+    }
+}
+
+// Fixed width types encode the same way standalone, so they can be table keys as is.
+macro_rules! impl_key_codec_for_memcomparable {
+    ($($ty:ty),+) => {
+        $(
+            impl KeyCodec for $ty {
+                #[inline]
+                fn encode_key(&self) -> Bytes {
+                    encode(self)
+                }
+
+                #[inline]
+                fn decode_key(key: &[u8]) -> Result<Self, CodecError> {
+                    decode(key)
+                }
+            }
+        )+
+    };
+}
+
+impl_key_codec_for_memcomparable!(i8, i16, i32, i64, f32, f64, bool);
+
+macro_rules! impl_key_codec_for_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: Memcomparable),+> KeyCodec for ($($name,)+) {
+            #[inline]
+            fn encode_key(&self) -> Bytes {
+                encode(self)
+            }
+
+            #[inline]
+            fn decode_key(key: &[u8]) -> Result<Self, CodecError> {
+                decode(key)
+            }
+        }
+    };
+}
+
+impl_key_codec_for_tuple!(A);
+impl_key_codec_for_tuple!(A, B);
+impl_key_codec_for_tuple!(A, B, C);
+impl_key_codec_for_tuple!(A, B, C, D);
+impl_key_codec_for_tuple!(A, B, C, D, E);
+impl_key_codec_for_tuple!(A, B, C, D, E, F);
+
+////////////////////////////////////////////////////////////////////////////////
+/// value codecs
+////////////////////////////////////////////////////////////////////////////////
+#[inline]
+pub fn encode_value<V: Serialize>(value: &V) -> Result<Vec<u8>, CodecError> {
+    rmp_serde::to_vec(value).map_err(|e| CodecError::new(e.to_string()))
+}
+
+#[inline]
+pub fn decode_value<V: DeserializeOwned>(value: &[u8]) -> Result<V, CodecError> {
+    rmp_serde::from_slice(value).map_err(|e| CodecError::new(e.to_string()))
+}
+
+#[test]
+fn test_uint_key_order() {
+    assert!(255u64.encode_key() < 256u64.encode_key());
+    assert!(65535u32.encode_key() < 65536u32.encode_key());
+    assert_eq!(u64::decode_key(&1024u64.encode_key()).unwrap(), 1024);
+    assert!(u64::decode_key(b"k111").is_err());
+}
+
+#[test]
+fn test_timestamp_key() {
+    assert_eq!(Secs(60).encode_key().as_ref(), Secs(60).to_key());
+    assert_eq!(Millis::decode_key(&Millis(60).encode_key()).unwrap(), Millis(60));
+}
+
+#[test]
+fn test_string_key() {
+    let key = "huobi.btc.usdt.1m".to_owned();
+    assert_eq!(String::decode_key(&key.encode_key()).unwrap(), key);
+    assert!(String::decode_key(&[255, 255]).is_err());
+}
+
+#[test]
+fn test_tuple_key() {
+    let key = (String::from("huobi"), 1.5f64, true).encode_key();
+    assert_eq!(<(String, f64, bool)>::decode_key(&key).unwrap(), ("huobi".to_owned(), 1.5, true));
+    assert_eq!(i64::decode_key(&(-1i64).encode_key()).unwrap(), -1);
+}
+
+#[test]
+fn test_value_codec() {
+    let value = ("huobi.btc.usdt.1m".to_owned(), 42u64, 1.5f64);
+    let encoded = encode_value(&value).unwrap();
+    let decoded: (String, u64, f64) = decode_value(&encoded).unwrap();
+    assert_eq!(decoded, value);
+    assert!(decode_value::<(String, u64)>(b"\xc1").is_err());
+}
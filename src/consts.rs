@@ -1,9 +1,8 @@
 use crate::types::*;
+use std::time::Duration;
 
 pub(in crate) const TABLE_ID_LEN: usize = 4;
 
-pub(in crate) const MAX_USERLAND_KEY_LEN: u8 = 4;
-
 // 1024 as BigEndian
 pub(in crate) const MIN_USERLAND_TABLE_ID: TableId = [0, 0, 4, 0];
 
@@ -22,5 +21,31 @@ pub(in crate) const ID_TO_NAME_TABLE_ID: TableId = [0, 0, 0, 2];
 // 3 as BigEndian
 pub(in crate) const DELETE_RANGE_HINT_TABLE_ID: TableId = [0, 0, 0, 3];
 
+// Leads the key of a delete range hint, whose from and to keys carry the table id. The from key
+// length follows as u32 BigEndian, then both keys.
+pub(in crate) const DELETE_RANGE_HINT_VERSION: u8 = 2;
+
+// Hints written by older versions, with both keys as a MessagePack tuple.
+pub(in crate) const MSGPACK_DELETE_RANGE_HINT_VERSION: u8 = 1;
+
+// 4 as BigEndian
+pub(in crate) const TABLE_META_TABLE_ID: TableId = [0, 0, 0, 4];
+
+// 5 as BigEndian
+pub(in crate) const CONSUMER_TABLE_ID: TableId = [0, 0, 0, 5];
+
+// A century, long enough to never expire while small enough to not overflow in rocksdb.
+pub(in crate) const WAL_TTL_FOREVER: u64 = 100 * 365 * 24 * 3600;
+pub(in crate) const WAL_TRIM_INTERVAL: Duration = Duration::from_secs(10);
+
 // 0 as BigEndian
 pub(in crate) const SEED_ITEM_ID: ItemId = [0, 0];
+
+// 1 as BigEndian
+pub(in crate) const FREE_TABLE_ID_ITEM_ID: ItemId = [0, 1];
+
+// 2 as BigEndian
+pub(in crate) const TABLE_GENERATION_ITEM_ID: ItemId = [0, 2];
+
+// 3 as BigEndian
+pub(in crate) const APPLIED_SN_ITEM_ID: ItemId = [0, 3];
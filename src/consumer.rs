@@ -0,0 +1,129 @@
+#[cfg(test)]
+use crate::db::Db;
+#[cfg(test)]
+use crate::options::Options;
+#[cfg(test)]
+use crate::utils::*;
+use crate::wal::{list_wal_files, WalFile};
+use crate::Error;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// The position of a change feed consumer, see `Db::register_consumer`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConsumerInfo {
+    pub name: String,
+    /// The sequence number the consumer committed last.
+    pub position: u64,
+    /// How many sequence numbers were written since `position`.
+    pub lag: u64,
+}
+
+/// Deletes the archived WAL files ahead of the one holding `sn`, or all of them if it is `None`.
+/// The file holding `sn` is kept, as `Db::get_updates_since` reads on from the batch holding it.
+pub(in crate) fn trim_wal_archive(db_path: &Path, sn: Option<u64>) -> Result<(), Error> {
+    let files = list_wal_files(db_path)?;
+    let first_sns: Vec<Option<u64>> = files.iter().map(WalFile::read_first_sn).collect();
+    for (i, file) in files.iter().enumerate() {
+        // Files without a batch, such as the ones just created, don't tell where the next starts.
+        let later_sn = first_sns.iter().skip(i + 1).flatten().next();
+        let read_past = match sn {
+            Some(sn) => matches!(later_sn, Some(&later_sn) if later_sn <= sn),
+            None => true,
+        };
+        if !file.archived || !read_past {
+            break;
+        }
+        match fs::remove_file(&file.path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_consumers() {
+    run_test("test_consumers", |db| {
+        let table = db.new_table("huobi.btc.usdt.1m").unwrap();
+        let sn = db.get_latest_sn();
+        assert_eq!(db.register_consumer("indexer").unwrap(), sn);
+        assert!(matches!(db.commit("archiver", sn), Err(Error::ConsumerNotFound(_))));
+        table.put(b"k1", b"v1").unwrap();
+        table.put(b"k2", b"v2").unwrap();
+        assert_eq!(db.register_consumer("indexer").unwrap(), sn);
+        let latest_sn = db.get_latest_sn();
+        assert_eq!(db.register_consumer("archiver").unwrap(), latest_sn);
+        let position = db.consumer_position("indexer").unwrap().unwrap();
+        let batch = db.get_updates_since(position).unwrap().next().unwrap().unwrap();
+        db.commit("indexer", batch.sn).unwrap();
+        assert_eq!(db.consumer_position("indexer").unwrap(), Some(batch.sn));
+        let consumers = db.consumers().unwrap();
+        let latest_sn = db.get_latest_sn();
+        assert_eq!(consumers.len(), 2);
+        assert_eq!(consumers[0].name, "archiver");
+        assert_eq!(consumers[1].name, "indexer");
+        assert_eq!(consumers[1].lag, latest_sn - batch.sn);
+        db.unregister_consumer("archiver").unwrap();
+        assert_eq!(db.consumer_position("archiver").unwrap(), None);
+        assert_eq!(db.consumers().unwrap().len(), 1);
+    })
+}
+
+#[test]
+fn test_retain_wal_for_consumers() {
+    let path = "./data/test_retain_wal_for_consumers";
+    let _ = Db::destroy(path);
+    let mut opts = Options::new();
+    opts.set_retain_wal_for_consumers(true);
+    let db = Db::new(path, &opts).unwrap();
+    let table = db.new_table("huobi.btc.usdt.1m").unwrap();
+    let sn = db.register_consumer("indexer").unwrap();
+    for i in 0..4u32 {
+        table.put(i.to_be_bytes(), b"v").unwrap();
+        db.flush().unwrap();
+    }
+    let count_archived = || {
+        let files = list_wal_files(Path::new(path)).unwrap();
+        files.iter().filter(|file| file.archived).count()
+    };
+    db.commit("indexer", sn).unwrap();
+    assert_eq!(count_archived(), 4);
+    // The registration and the commit are in the feed as well, to keep followers identical.
+    assert_eq!(db.get_updates_since(sn).unwrap().count(), 6);
+    let latest_sn = db.get_latest_sn();
+    db.commit("indexer", latest_sn - 1).unwrap();
+    assert_eq!(count_archived(), 1);
+    table.put(b"k", b"v").unwrap();
+    assert_eq!(db.get_updates_since(latest_sn - 1).unwrap().count(), 3);
+    assert!(matches!(db.commit("indexer", sn), Err(Error::InvalidPosition(_))));
+    let ahead_sn = db.get_latest_sn() + 1;
+    assert!(matches!(db.commit("indexer", ahead_sn), Err(Error::InvalidPosition(_))));
+    assert_eq!(db.consumer_position("indexer").unwrap(), Some(latest_sn - 1));
+    db.unregister_consumer("indexer").unwrap();
+    assert_eq!(count_archived(), 0);
+    // Without consumers, flushing drops the files it archives.
+    table.put(b"k", b"v").unwrap();
+    db.flush().unwrap();
+    assert_eq!(count_archived(), 0);
+    // And so does opening, which flushes the WAL it recovers.
+    table.put(b"k", b"v").unwrap();
+    drop(table);
+    drop(db);
+    let db = Db::new(path, &opts).unwrap();
+    assert_eq!(count_archived(), 0);
+    drop(db);
+    Db::destroy(path).unwrap();
+}
+
+#[test]
+fn test_retain_wal_for_consumers_keeps_wal_options() {
+    let mut opts = Options::new();
+    opts.set_wal_ttl_seconds(60);
+    opts.set_wal_size_limit_mb(512);
+    opts.set_retain_wal_for_consumers(true);
+    opts.set_retain_wal_for_consumers(false);
+    assert_eq!(opts.wal_ttl_seconds, 60);
+    assert_eq!(opts.wal_size_limit_mb, 512);
+}
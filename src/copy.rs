@@ -0,0 +1,153 @@
+use crate::snapshot::{Snapshot, SnapshotTable};
+use crate::types::*;
+#[cfg(test)]
+use crate::utils::*;
+#[cfg(test)]
+use crate::Error;
+use bytes::Bytes;
+use std::ops::{Bound, RangeBounds};
+
+pub struct CopyOptions {
+    pub(in crate) batch_size: usize,
+    pub(in crate) range: (Bound<Bytes>, Bound<Bytes>),
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CopyOptions {
+    pub fn new() -> Self {
+        CopyOptions { batch_size: 4 << 20, range: (Bound::Unbounded, Bound::Unbounded) }
+    }
+
+    /// Sets how many bytes of keys and values are written per batch.
+    pub fn set_batch_size(&mut self, size: usize) {
+        self.batch_size = size;
+    }
+
+    /// Restricts the copy to the keys that fall in `range`.
+    pub fn set_range<K, R>(&mut self, range: R)
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>, {
+        let to_bytes = |bound: Bound<&K>| match bound {
+            Bound::Included(key) => Bound::Included(Bytes::copy_from_slice(key.as_ref())),
+            Bound::Excluded(key) => Bound::Excluded(Bytes::copy_from_slice(key.as_ref())),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        self.range = (to_bytes(range.start_bound()), to_bytes(range.end_bound()));
+    }
+}
+
+/// How far a copy has got, reported after every batch.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CopyProgress {
+    pub entries: u64,
+    pub bytes: u64,
+    pub batches: u64,
+}
+
+/// A table frozen at the moment it was forked, which costs a snapshot and nothing else.
+pub struct TableFork<'a> {
+    snapshot: Snapshot<'a>,
+    name: String,
+    id: TableId,
+}
+
+impl<'a> TableFork<'a> {
+    #[inline]
+    pub(in crate) fn new(snapshot: Snapshot<'a>, name: String, id: TableId) -> Self {
+        TableFork { snapshot, name, id }
+    }
+
+    #[inline]
+    pub fn table(&self) -> SnapshotTable<'_> {
+        self.snapshot.build_table(self.id)
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn snapshot(&self) -> &Snapshot<'a> {
+        &self.snapshot
+    }
+}
+
+#[test]
+fn test_copy_table() {
+    run_test("test_copy_table", |db| {
+        let src = db.new_table("huobi.btc.usdt.1m").unwrap();
+        for i in 0..100u32 {
+            src.put(i.to_be_bytes(), [0; 10]).unwrap();
+        }
+        let mut opts = CopyOptions::new();
+        opts.set_batch_size(100);
+        let mut reports = vec![];
+        let progress = db
+            .copy_table_with_progress("huobi.btc.usdt.1m", "huobi.btc.usdt.1m.copy", &opts, |p| {
+                reports.push(*p)
+            })
+            .unwrap();
+        assert_eq!(progress.entries, 100);
+        assert_eq!(progress.bytes, 100 * 14);
+        assert_eq!(progress.batches, 13);
+        assert_eq!(reports.len(), 13);
+        assert_eq!(*reports.last().unwrap(), progress);
+        let dst = db.new_table("huobi.btc.usdt.1m.copy").unwrap();
+        assert_ne!(dst.id(), src.id());
+        assert!(src.iter().eq(dst.iter()));
+    })
+}
+
+#[test]
+fn test_copy_table_range() {
+    run_test("test_copy_table_range", |db| {
+        let src = db.new_table("huobi.btc.usdt.1m").unwrap();
+        for key in &["k1", "k2", "k3", "k4"] {
+            src.put(key, b"v").unwrap();
+        }
+        let mut opts = CopyOptions::new();
+        opts.set_range("k2"..="k3");
+        let progress = db.copy_table("huobi.btc.usdt.1m", "huobi.btc.usdt.5m", &opts).unwrap();
+        assert_eq!(progress.entries, 2);
+        let keys: Vec<Bytes> =
+            db.new_table("huobi.btc.usdt.5m").unwrap().iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec!["k2", "k3"]);
+    })
+}
+
+#[test]
+fn test_copy_table_errors() {
+    run_test("test_copy_table_errors", |db| {
+        db.new_table("huobi.btc.usdt.1m").unwrap();
+        db.new_table("huobi.btc.usdt.5m").unwrap();
+        let opts = CopyOptions::new();
+        let result = db.copy_table("huobi.btc.usdt.1m", "huobi.btc.usdt.5m", &opts);
+        assert!(matches!(result, Err(Error::TableAlreadyExists(_))));
+        let result = db.copy_table("huobi.btc.usdt.15m", "huobi.btc.usdt.30m", &opts);
+        assert!(matches!(result, Err(Error::TableNotFound(_))));
+        assert!(db.get_table_id_by_name("huobi.btc.usdt.30m").unwrap().is_none());
+    })
+}
+
+#[test]
+fn test_fork_table() {
+    run_test("test_fork_table", |db| {
+        let table = db.new_table("huobi.btc.usdt.1m").unwrap();
+        table.put(b"k1", b"v1").unwrap();
+        let fork = db.fork_table("huobi.btc.usdt.1m").unwrap();
+        table.put(b"k1", b"v2").unwrap();
+        table.put(b"k2", b"v2").unwrap();
+        db.rename_table("huobi.btc.usdt.1m", "huobi.btc.usdt.5m").unwrap();
+        assert_eq!(fork.name(), "huobi.btc.usdt.1m");
+        assert_eq!(fork.table().get(b"k1").unwrap().unwrap(), b"v1");
+        assert_eq!(fork.table().iter().count(), 1);
+        assert!(matches!(db.fork_table("huobi.btc.usdt.1m"), Err(Error::TableNotFound(_))));
+    })
+}
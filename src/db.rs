@@ -1,91 +1,321 @@
 use crate::batch_x::BatchX;
+use crate::catalog::{glob_literal_prefix, CatalogIterator};
+use crate::codec::{decode_value, encode_value, KeyCodec};
 use crate::consts::*;
+use crate::consumer::{trim_wal_archive, ConsumerInfo};
+use crate::copy::{CopyOptions, CopyProgress, TableFork};
+use crate::decoded_update::DecodedUpdateIterator;
+use crate::memcomparable::prefix_end;
 use crate::options::Options;
+use crate::owned_table::OwnedTable;
+use crate::raw_batch::RawBatch;
+use crate::snapshot::Snapshot;
+use crate::subscription::{Subscription, WriteSignal};
 use crate::table::Table;
+use crate::table_filter::{FilteredUpdateIterator, TableFilter};
+use crate::table_meta::TableMeta;
+use crate::table_stats::{estimate_range, TableStats};
+use crate::timestamp::Millis;
+use crate::typed_table::TypedTable;
 use crate::types::*;
+use crate::update::Update;
+use crate::update_batch::UpdateBatch;
 use crate::update_iterator::UpdateIterator;
 use crate::utils::*;
+use crate::wal::earliest_wal_sn;
 use crate::Error;
-use rocksdb::DB;
-use rocksdb::{ReadOptions, WriteBatch};
+use bytes::Bytes;
+use rocksdb::ReadOptions;
+use rocksdb::{LiveFile, DB};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
+use std::time::Instant;
 
 pub struct Db {
     pub(in crate) inner: DB,
+    // Serializes the changes to the name-to-id, id-to-name and consumer tables.
+    catalog_lock: Mutex<()>,
+    // Shared by the writes and held alone while taking a snapshot, so that none lands between
+    // reading the latest sn and taking the snapshot.
+    write_lock: RwLock<()>,
+    pub(in crate) write_signal: WriteSignal,
+    retain_wal_for_consumers: bool,
+    last_wal_trim: Mutex<Instant>,
 }
 
 impl Db {
     #[inline]
     pub fn new<P: AsRef<Path>>(path: P, opts: &Options) -> Result<Db, Error> {
-        Ok(Db { inner: DB::open(&opts.inner, path)? })
+        let db = Db {
+            inner: DB::open(&opts.inner, path)?,
+            catalog_lock: Mutex::new(()),
+            write_lock: RwLock::new(()),
+            write_signal: WriteSignal::default(),
+            retain_wal_for_consumers: opts.retain_wal_for_consumers,
+            last_wal_trim: Mutex::new(Instant::now()),
+        };
+        // Opening flushes the recovered WAL into the archive.
+        db.trim_wal()?;
+        Ok(db)
     }
 
     #[inline]
     pub fn destroy<P: AsRef<Path>>(path: P) -> Result<(), Error> {
-        DB::destroy(&Options::new().inner, path)
+        Ok(DB::destroy(&Options::new().inner, path)?)
     }
 
+    /// Opens a table, creating it if it doesn't exist, see `get_or_create_table`.
     #[inline]
-    pub fn new_table(&self, name: &str) -> Result<Table, Error> {
-        if let Some(id) = self.get_table_id_by_name(name)? {
-            Ok(Table {
-                db: &self,
-                id,
-                anchor: build_userland_table_anchor(id, MAX_USERLAND_KEY_LEN),
-            })
+    pub fn new_table(&self, name: &str) -> Result<Table<'_>, Error> {
+        Ok(self.get_or_create_table(name)?.0)
+    }
+
+    /// Opens a table, creating it if it doesn't exist, and tells whether it was created.
+    pub fn get_or_create_table(&self, name: &str) -> Result<(Table<'_>, bool), Error> {
+        if let Some(table) = self.open_table(name)? {
+            return Ok((table, false));
+        }
+        let _guard = self.lock_catalog();
+        if let Some(table) = self.open_table(name)? {
+            Ok((table, false))
         } else {
-            Ok(self.create_table(name)?)
+            Ok((self.create_table(name)?, true))
         }
     }
-    pub fn destroy_table(&self, name: &str) -> Result<(), Error> {
-        let mut batch = WriteBatch::default();
-        if let Some(id) = self.get_table_id_by_name(name)? {
-            batch.delete(&build_name_to_id_table_inner_key(name));
-            batch.delete(&build_id_to_name_table_inner_key(id));
-            let anchor = build_userland_table_anchor(id, MAX_USERLAND_KEY_LEN);
-            batch.delete(&build_delete_range_hint_table_inner_key(&id, &anchor));
-            batch.delete_range(id.as_ref(), anchor.as_ref());
+
+    /// Opens a table if it exists, never creating one.
+    #[inline]
+    pub fn open_table(&self, name: &str) -> Result<Option<Table<'_>>, Error> {
+        match self.get_table_id_by_name(name)? {
+            Some(id) => Ok(Some(Table::new(self, id, build_table_anchor(id)))),
+            None => Ok(None),
         }
-        self.inner.write(batch)
     }
 
-    pub fn truncate_table(&self, name: &str) -> Result<(), Error> {
-        let mut batch = WriteBatch::default();
-        if let Some(id) = self.get_table_id_by_name(name)? {
-            let anchor = build_userland_table_anchor(id, MAX_USERLAND_KEY_LEN);
-            batch.delete(&build_delete_range_hint_table_inner_key(&id, &anchor));
-            batch.delete_range(id.as_ref(), anchor.as_ref());
+    /// Opens the table with the given id if it exists, never creating one.
+    #[inline]
+    pub fn table_by_id(&self, id: TableId) -> Result<Option<Table<'_>>, Error> {
+        if self.inner.get_pinned(build_id_to_name_table_inner_key(id))?.is_some() {
+            Ok(Some(Table::new(self, id, build_table_anchor(id))))
+        } else {
+            Ok(None)
         }
-        self.inner.write(batch)
     }
 
+    /// Like `new_table`, but returns a handle that can outlive the borrow of `self`.
+    #[inline]
+    pub fn new_owned_table(self: &Arc<Self>, name: &str) -> Result<OwnedTable, Error> {
+        let id = self.new_table(name)?.id;
+        Ok(OwnedTable::new(self.clone(), id, build_table_anchor(id)))
+    }
+
+    #[inline]
+    pub fn new_typed_table<K, V>(&self, name: &str) -> Result<TypedTable<'_, K, V>, Error>
+    where
+        K: KeyCodec,
+        V: Serialize + DeserializeOwned, {
+        Ok(TypedTable::new(self.new_table(name)?))
+    }
+
+    /// Destroys a table and frees its id for reuse, so handles to it must not be used afterwards.
+    pub fn destroy_table(&self, name: &str) -> Result<(), Error> {
+        let _guard = self.lock_catalog();
+        let id = self.get_existing_table_id(name)?;
+        let mut batch = RawBatch::default();
+        batch.delete(build_name_to_id_table_inner_key(name));
+        release_table(&mut batch, id);
+        self.write_raw(batch)
+    }
+
+    pub fn truncate_table(&self, name: &str) -> Result<(), Error> {
+        let _guard = self.lock_catalog();
+        let id = self.get_existing_table_id(name)?;
+        let mut batch = RawBatch::default();
+        clear_table(&mut batch, id);
+        self.write_raw(batch)
+    }
+
+    /// Renames a table, failing if `new_name` is taken.
     pub fn rename_table(&self, old_name: &str, new_name: &str) -> Result<(), Error> {
-        let mut batch = WriteBatch::default();
-        if let Some(id) = self.get_table_id_by_name(old_name)? {
-            let id_to_name_table_inner_key = build_id_to_name_table_inner_key(id);
-            batch.delete(&build_name_to_id_table_inner_key(old_name));
-            batch.delete(&id_to_name_table_inner_key);
-            batch.put(build_name_to_id_table_inner_key(new_name), id);
-            batch.put(id_to_name_table_inner_key, new_name);
+        let _guard = self.lock_catalog();
+        let id = self.get_existing_table_id(old_name)?;
+        if self.get_table_id_by_name(new_name)?.is_some() {
+            return Err(Error::TableAlreadyExists(new_name.to_owned()));
+        }
+        let mut batch = RawBatch::default();
+        batch.delete(build_name_to_id_table_inner_key(old_name));
+        register_table(&mut batch, new_name, id);
+        self.write_raw(batch)
+    }
+
+    /// Renames a table, destroying the table that was named `new_name`, if any.
+    pub fn rename_table_overwrite(&self, old_name: &str, new_name: &str) -> Result<(), Error> {
+        let _guard = self.lock_catalog();
+        let id = self.get_existing_table_id(old_name)?;
+        let mut batch = RawBatch::default();
+        match self.get_table_id_by_name(new_name)? {
+            Some(replaced_id) if replaced_id == id => return Ok(()),
+            Some(replaced_id) => release_table(&mut batch, replaced_id),
+            None => {}
         }
-        self.inner.write(batch)
+        batch.delete(build_name_to_id_table_inner_key(old_name));
+        register_table(&mut batch, new_name, id);
+        self.write_raw(batch)
+    }
+
+    /// Exchanges the names of two tables, so that each name refers to the other's data.
+    pub fn swap_tables(&self, name: &str, other_name: &str) -> Result<(), Error> {
+        let _guard = self.lock_catalog();
+        let id = self.get_existing_table_id(name)?;
+        let other_id = self.get_existing_table_id(other_name)?;
+        let mut batch = RawBatch::default();
+        register_table(&mut batch, other_name, id);
+        register_table(&mut batch, name, other_id);
+        self.write_raw(batch)
+    }
+
+    /// Replaces the metadata of a table, keeping the `created_at` recorded at its creation. The
+    /// change is written to the change feed like any other write.
+    pub fn set_table_meta(&self, name: &str, meta: &TableMeta) -> Result<(), Error> {
+        let _guard = self.lock_catalog();
+        let id = self.get_existing_table_id(name)?;
+        let stored = self.get_table_meta_by_id(id)?;
+        let created_at = stored.and_then(|stored| stored.created_at).or(meta.created_at);
+        let meta = TableMeta { created_at, ..meta.clone() };
+        self.put_raw(build_table_meta_table_inner_key(id), encode_value(&meta)?)
     }
 
-    pub fn get_tables(&self) -> Vec<(String, u32)> {
+    pub fn get_table_meta(&self, name: &str) -> Result<Option<TableMeta>, Error> {
+        self.get_table_meta_by_id(self.get_existing_table_id(name)?)
+    }
+
+    fn get_table_meta_by_id(&self, id: TableId) -> Result<Option<TableMeta>, Error> {
+        match self.inner.get(build_table_meta_table_inner_key(id))? {
+            Some(value) => Ok(Some(decode_value(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Iterates over every table in name order.
+    #[inline]
+    pub fn tables(&self) -> CatalogIterator<'_> {
+        CatalogIterator::new(&self.inner, "", None)
+    }
+
+    #[inline]
+    pub fn tables_with_prefix(&self, prefix: &str) -> CatalogIterator<'_> {
+        CatalogIterator::new(&self.inner, prefix, None)
+    }
+
+    /// Iterates over the tables whose names match a glob, in which `*` matches any sequence of
+    /// characters and `?` exactly one.
+    #[inline]
+    pub fn tables_matching(&self, pattern: &str) -> CatalogIterator<'_> {
+        CatalogIterator::new(&self.inner, glob_literal_prefix(pattern), Some(pattern))
+    }
+
+    /// Copies the entries of `src_name`, as of when the copy starts, into a new table named
+    /// `dst_name`. A copy that fails midway leaves the part copied so far in `dst_name`.
+    #[inline]
+    pub fn copy_table(
+        &self, src_name: &str, dst_name: &str, opts: &CopyOptions,
+    ) -> Result<CopyProgress, Error> {
+        self.copy_table_with_progress(src_name, dst_name, opts, |_| {})
+    }
+
+    /// Like `copy_table`, calling `progress` after every batch written.
+    pub fn copy_table_with_progress<F: FnMut(&CopyProgress)>(
+        &self, src_name: &str, dst_name: &str, opts: &CopyOptions, mut progress: F,
+    ) -> Result<CopyProgress, Error> {
+        let snapshot = self.snapshot();
+        let src = match snapshot.table(src_name)? {
+            Some(src) => src,
+            None => return Err(Error::TableNotFound(src_name.to_owned())),
+        };
+        let dst = {
+            let _guard = self.lock_catalog();
+            if self.get_table_id_by_name(dst_name)?.is_some() {
+                return Err(Error::TableAlreadyExists(dst_name.to_owned()));
+            }
+            self.create_table(dst_name)?
+        };
+        let mut result = CopyProgress::default();
+        let mut batch = dst.batch();
+        let mut batch_bytes = 0;
+        let mut iter = src.range(opts.range.clone());
+        loop {
+            let entry = iter.next();
+            if let Some((key, value)) = &entry {
+                batch.put(key, value);
+                batch_bytes += key.len() + value.len();
+                result.entries += 1;
+            }
+            if batch_bytes >= opts.batch_size || (entry.is_none() && batch_bytes > 0) {
+                dst.write(std::mem::replace(&mut batch, dst.batch()))?;
+                result.bytes += batch_bytes as u64;
+                result.batches += 1;
+                batch_bytes = 0;
+                progress(&result);
+            }
+            if entry.is_none() {
+                break;
+            }
+        }
+        iter.status()?;
+        Ok(result)
+    }
+
+    /// Returns a read-only view of a table as of now, which later writes don't change.
+    #[inline]
+    pub fn fork_table(&self, name: &str) -> Result<TableFork<'_>, Error> {
+        let snapshot = self.snapshot();
+        match snapshot.get_table_id_by_name(name)? {
+            Some(id) => Ok(TableFork::new(snapshot, name.to_owned(), id)),
+            None => Err(Error::TableNotFound(name.to_owned())),
+        }
+    }
+
+    pub fn table_stats(&self, name: &str) -> Result<TableStats, Error> {
+        let id = self.get_existing_table_id(name)?;
+        self.build_table_stats(&self.inner.live_files()?, name.to_owned(), id)
+    }
+
+    /// Returns the stats of every table in name order.
+    pub fn all_table_stats(&self) -> Result<Vec<TableStats>, Error> {
+        let files = self.inner.live_files()?;
+        let mut result = Vec::new();
+        for entry in self.tables() {
+            let (name, id) = entry?;
+            result.push(self.build_table_stats(&files, name, id)?);
+        }
+        Ok(result)
+    }
+
+    /// Flushes the memtables to sst files.
+    #[inline]
+    pub fn flush(&self) -> Result<(), Error> {
+        self.inner.flush()?;
+        self.trim_wal()
+    }
+
+    /// Returns every table in id order.
+    pub fn get_tables(&self) -> Result<Vec<(String, u32)>, Error> {
         let mut result: Vec<(String, u32)> = Vec::new();
         let mut opts = ReadOptions::default();
         opts.set_prefix_same_as_start(true);
         let mut iter = self.inner.raw_iterator_opt(opts);
         iter.seek(ID_TO_NAME_TABLE_ID);
-        while iter.valid() {
-            let key = iter.key().unwrap();
-            let value = iter.value().unwrap();
+        while let (Some(key), Some(value)) = (iter.key(), iter.value()) {
             let id = u8s_to_u32(extract_key(key));
-            let name = std::str::from_utf8(value).unwrap().to_string();
+            let name = decode_table_name(value)?;
             result.push((name, id));
             iter.next();
         }
-        result
+        iter.status()?;
+        Ok(result)
     }
 
     #[inline]
@@ -98,25 +328,331 @@ impl Db {
         }
     }
 
+    #[inline]
+    fn get_existing_table_id(&self, name: &str) -> Result<TableId, Error> {
+        match self.get_table_id_by_name(name)? {
+            Some(id) => Ok(id),
+            None => Err(Error::TableNotFound(name.to_owned())),
+        }
+    }
+
     #[inline]
     pub fn get_table_name_by_id(&self, id: TableId) -> Result<Option<String>, Error> {
         let id_to_name_table_inner_key = build_id_to_name_table_inner_key(id);
         if let Some(name) = self.inner.get(id_to_name_table_inner_key)? {
-            Ok(Some(std::str::from_utf8(name.as_ref()).unwrap().to_string()))
+            Ok(Some(decode_table_name(&name)?))
         } else {
             Ok(None)
         }
     }
 
+    /// Returns how many times the id has been reused after its table was destroyed, which tells
+    /// the incarnations of the id apart.
+    #[inline]
+    pub fn get_table_generation(&self, id: TableId) -> Result<u32, Error> {
+        let key = build_info_table_item_inner_key(TABLE_GENERATION_ITEM_ID, id);
+        match self.inner.get(key)? {
+            Some(value) => decode_table_generation(&value),
+            None => Ok(0),
+        }
+    }
+
+    /// Returns the name of the table with `id` unless the id has been reused since `generation`.
+    pub(in crate) fn get_table_name_by_generation(
+        &self, id: TableId, generation: u32,
+    ) -> Result<Option<String>, Error> {
+        let _guard = self.lock_catalog();
+        if self.get_table_generation(id)? != generation {
+            return Ok(None);
+        }
+        self.get_table_name_by_id(id)
+    }
+
+    /// Returns the generations of the table ids as of `sn`, undoing the reuses the feed recorded
+    /// since. Ids missing from the map were never reused.
+    fn get_table_generations_at(&self, sn: u64) -> Result<HashMap<TableId, u32>, Error> {
+        let (latest_sn, mut generations) = {
+            let _guard = self.lock_catalog();
+            (self.get_latest_sn(), self.get_table_generations()?)
+        };
+        let mut undone = HashSet::new();
+        for batch in self.get_updates_until(sn, latest_sn)? {
+            for update in &batch?.updates {
+                if let Update::Put { key, value } = update {
+                    match extract_table_generation_id(key) {
+                        Some(id) if undone.insert(id) => {
+                            generations
+                                .insert(id, decode_table_generation(value)?.saturating_sub(1));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        Ok(generations)
+    }
+
+    fn get_table_generations(&self) -> Result<HashMap<TableId, u32>, Error> {
+        let lower_bound = build_info_table_inner_key(TABLE_GENERATION_ITEM_ID);
+        let mut opts = ReadOptions::default();
+        opts.set_total_order_seek(true);
+        opts.set_iterate_upper_bound(prefix_end(&lower_bound).unwrap().to_vec());
+        opts.set_iterate_lower_bound(lower_bound.to_vec());
+        let mut iter = self.inner.raw_iterator_opt(opts);
+        iter.seek_to_first();
+        let mut generations = HashMap::new();
+        while let (Some(key), Some(value)) = (iter.key(), iter.value()) {
+            if let Some(id) = extract_table_generation_id(key) {
+                generations.insert(id, decode_table_generation(value)?);
+            }
+            iter.next();
+        }
+        iter.status()?;
+        Ok(generations)
+    }
+
     #[inline]
     pub fn get_latest_sn(&self) -> u64 {
         self.inner.latest_sequence_number()
     }
 
+    /// Takes a snapshot that every table read through it is consistent with.
     #[inline]
+    pub fn snapshot(&self) -> Snapshot<'_> {
+        Snapshot::new(self)
+    }
+
+    /// Iterates over the batches written after the one holding `sn`, failing with `WalGap` if
+    /// the WAL no longer holds that batch or breaks off later on. An `sn` of 0 reads whatever the
+    /// WAL still holds.
     pub fn get_updates_since(&self, sn: u64) -> Result<UpdateIterator, Error> {
+        let latest_sn = self.get_latest_sn();
         let iter = self.inner.get_updates_since(sn)?;
-        Ok(UpdateIterator::new(iter))
+        // The raw iterator skips the batch holding `sn`, which must still be in the WAL for the
+        // next one to be read. Looked up after the iterator is created, which is conservative
+        // against purges in between.
+        if sn > 0 && sn < latest_sn {
+            match earliest_wal_sn(self.inner.path())? {
+                Some(earliest_sn) if earliest_sn <= sn => {}
+                found => return Err(Error::WalGap { expected: sn, found }),
+            }
+        }
+        Ok(UpdateIterator::with_next_sn(iter, self.inner.path(), None))
+    }
+
+    /// Goes on reading the feed after `sn`, where the next batch is known to start at `next_sn`.
+    pub(in crate) fn resume_updates(
+        &self, sn: u64, next_sn: Option<u64>,
+    ) -> Result<UpdateIterator, Error> {
+        match next_sn {
+            Some(_) => {
+                let iter = self.inner.get_updates_since(sn)?;
+                Ok(UpdateIterator::with_next_sn(iter, self.inner.path(), next_sn))
+            }
+            None => self.get_updates_since(sn),
+        }
+    }
+
+    /// Like `get_updates_since`, but yields only the updates of the tables selected by `filter`.
+    /// Tables are selected by their names as of `sn`, then by the catalog changes read from the
+    /// feed. The feed is read once more up front, to undo the catalog changes made since `sn`.
+    pub fn get_updates_since_for(
+        &self, sn: u64, filter: &TableFilter,
+    ) -> Result<FilteredUpdateIterator, Error> {
+        let inner = self.get_updates_since(sn)?;
+        let mut ids: HashSet<TableId> = filter.ids.iter().copied().collect();
+        if !filter.prefixes.is_empty() {
+            for (id, name) in self.get_table_names_at(sn)? {
+                if filter.matches_name(&name) {
+                    ids.insert(id);
+                }
+            }
+        }
+        Ok(FilteredUpdateIterator::new(inner, filter.clone(), ids))
+    }
+
+    /// Returns the names of the tables as of `sn`, undoing the catalog changes the feed recorded
+    /// since.
+    fn get_table_names_at(&self, sn: u64) -> Result<HashMap<TableId, String>, Error> {
+        let (latest_sn, mut names) = {
+            let _guard = self.lock_catalog();
+            let names = self.tables().map(|entry| entry.map(|(name, id)| (id, name)));
+            (self.get_latest_sn(), names.collect::<Result<HashMap<TableId, String>, Error>>()?)
+        };
+        let mut batches = vec![];
+        for batch in self.get_updates_until(sn, latest_sn)? {
+            let batch = batch?;
+            if batch.updates.iter().any(|u| matches!(u.table_id(), Ok(ID_TO_NAME_TABLE_ID))) {
+                batches.push(batch);
+            }
+        }
+        for batch in batches.iter().rev() {
+            undo_catalog_changes(&mut names, batch)?;
+        }
+        Ok(names)
+    }
+
+    /// Reads the feed after `sn` up to the batch holding `latest_sn`.
+    fn get_updates_until(
+        &self, sn: u64, latest_sn: u64,
+    ) -> Result<impl Iterator<Item = Result<UpdateBatch, Error>>, Error> {
+        let iter = self.get_updates_since(sn)?;
+        Ok(iter.take_while(move |batch| !matches!(batch, Ok(batch) if batch.sn > latest_sn)))
+    }
+
+    /// Like `get_updates_since`, but splits every batch into table-level updates keyed by user
+    /// keys and the raw writes to system tables. The feed is read once more up front, to tell
+    /// the generations of reused table ids apart.
+    pub fn get_decoded_updates_since(&self, sn: u64) -> Result<DecodedUpdateIterator<'_>, Error> {
+        let inner = self.get_updates_since(sn)?;
+        Ok(DecodedUpdateIterator::new(self, inner, self.get_table_generations_at(sn)?))
+    }
+
+    /// Applies a batch read from the change feed of a leader, catalog changes included, so that
+    /// this database follows it. Batches at or below the last applied sequence number are skipped,
+    /// which makes it safe to resume from `get_applied_sn`. Tables must not be changed locally
+    /// while following.
+    pub fn apply(&self, batch: &UpdateBatch) -> Result<(), Error> {
+        let _guard = self.lock_catalog();
+        if matches!(self.get_applied_sn()?, Some(sn) if batch.sn <= sn) {
+            return Ok(());
+        }
+        let mut raw_batch = RawBatch::default();
+        for update in &batch.updates {
+            match update {
+                Update::Put { key, value } => raw_batch.put(key, value),
+                Update::Delete { key } => raw_batch.delete(key),
+                Update::DeleteRange { from_key, to_key } => {
+                    raw_batch.delete_range(from_key, to_key)
+                }
+            }
+        }
+        raw_batch.put(build_info_table_inner_key(APPLIED_SN_ITEM_ID), batch.sn.to_be_bytes());
+        self.write_raw(raw_batch)
+    }
+
+    /// Returns the sequence number of the last batch applied from a leader, if any.
+    pub fn get_applied_sn(&self) -> Result<Option<u64>, Error> {
+        match self.inner.get_pinned(build_info_table_inner_key(APPLIED_SN_ITEM_ID))? {
+            Some(value) if value.len() == 8 => Ok(Some(u8s_to_u64(&value))),
+            Some(value) => Err(Error::corruption(format!("Invalid applied sn {:?}", &value[..]))),
+            None => Ok(None),
+        }
+    }
+
+    /// Tails the change feed from `sn`, see `get_updates_since`, waiting for new batches instead of
+    /// ending.
+    #[inline]
+    pub fn subscribe(&self, sn: u64) -> Subscription<'_> {
+        Subscription::new(self, sn)
+    }
+
+    /// Registers a durable consumer of the change feed at the latest sequence number, unless it
+    /// is registered already. Returns its position, which it reads the feed from with
+    /// `get_updates_since`.
+    pub fn register_consumer(&self, name: &str) -> Result<u64, Error> {
+        let position = {
+            let _guard = self.lock_catalog();
+            if let Some(position) = self.consumer_position(name)? {
+                return Ok(position);
+            }
+            let position = self.get_latest_sn();
+            self.put_raw(build_consumer_table_inner_key(name), position.to_be_bytes())?;
+            position
+        };
+        // Drops the files archived while no consumer held them back.
+        self.trim_wal()?;
+        Ok(position)
+    }
+
+    /// Records that a consumer is done with the batch holding `sn`, which can't be ahead of the
+    /// latest write or behind its last commit. Commits show up in the change feed as writes to a
+    /// system table. With `set_retain_wal_for_consumers`, the WAL files every consumer is done
+    /// with are deleted.
+    pub fn commit(&self, name: &str, sn: u64) -> Result<(), Error> {
+        {
+            let _guard = self.lock_catalog();
+            let position = match self.consumer_position(name)? {
+                Some(position) => position,
+                None => return Err(Error::ConsumerNotFound(name.to_owned())),
+            };
+            let latest_sn = self.get_latest_sn();
+            if sn < position || sn > latest_sn {
+                return Err(Error::InvalidPosition(format!(
+                    "Position {} of consumer {} is out of [{}, {}]",
+                    sn, name, position, latest_sn
+                )));
+            }
+            self.put_raw(build_consumer_table_inner_key(name), sn.to_be_bytes())?;
+        }
+        self.trim_wal()
+    }
+
+    /// Returns the sequence number a consumer committed last, if it is registered.
+    pub fn consumer_position(&self, name: &str) -> Result<Option<u64>, Error> {
+        match self.inner.get_pinned(build_consumer_table_inner_key(name))? {
+            Some(value) => Ok(Some(decode_consumer_position(name, &value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Unregisters a consumer, so that the WAL is no longer retained for it.
+    pub fn unregister_consumer(&self, name: &str) -> Result<(), Error> {
+        {
+            let _guard = self.lock_catalog();
+            if self.consumer_position(name)?.is_none() {
+                return Err(Error::ConsumerNotFound(name.to_owned()));
+            }
+            self.delete_raw(build_consumer_table_inner_key(name))?;
+        }
+        self.trim_wal()
+    }
+
+    /// Returns every consumer in name order, with how far behind the latest write it is.
+    pub fn consumers(&self) -> Result<Vec<ConsumerInfo>, Error> {
+        let latest_sn = self.get_latest_sn();
+        let mut result = Vec::new();
+        let mut opts = ReadOptions::default();
+        opts.set_prefix_same_as_start(true);
+        let mut iter = self.inner.raw_iterator_opt(opts);
+        iter.seek(CONSUMER_TABLE_ID);
+        while let (Some(key), Some(value)) = (iter.key(), iter.value()) {
+            let name = decode_consumer_name(extract_key(key))?;
+            let position = decode_consumer_position(&name, value)?;
+            result.push(ConsumerInfo { name, position, lag: latest_sn.saturating_sub(position) });
+            iter.next();
+        }
+        iter.status()?;
+        Ok(result)
+    }
+
+    /// Deletes the WAL files past the slowest consumer, if they are retained for consumers.
+    fn trim_wal(&self) -> Result<(), Error> {
+        if !self.retain_wal_for_consumers {
+            return Ok(());
+        }
+        let position = self.consumers()?.iter().map(|consumer| consumer.position).min();
+        trim_wal_archive(self.inner.path(), position)
+    }
+
+    /// Trims the WAL archive at most every `WAL_TRIM_INTERVAL`, to drop the files archived by
+    /// the background flushes.
+    fn trim_wal_periodically(&self) {
+        if !self.retain_wal_for_consumers {
+            return;
+        }
+        // Another writer is already trimming.
+        let mut last_wal_trim = match self.last_wal_trim.try_lock() {
+            Ok(last_wal_trim) => last_wal_trim,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => return,
+        };
+        if last_wal_trim.elapsed() < WAL_TRIM_INTERVAL {
+            return;
+        }
+        *last_wal_trim = Instant::now();
+        // The write went through, a failed trim leaves the files to the next one.
+        let _ = self.trim_wal();
     }
 
     #[inline]
@@ -126,46 +662,240 @@ impl Db {
 
     #[inline]
     pub fn write(&self, b: BatchX) -> Result<(), Error> {
-        self.inner.write(b.inner)
+        self.write_raw(b.inner)
     }
 
-    fn create_table(&self, name: &str) -> Result<Table, Error> {
-        let name_to_id_table_inner_key = build_name_to_id_table_inner_key(name);
-        let id = self.generate_next_table_id()?;
-        let id_to_name_table_inner_key = build_id_to_name_table_inner_key(id);
-        self.register_table(name_to_id_table_inner_key, id, id_to_name_table_inner_key, name)?;
-        let anchor = build_userland_table_anchor(id, MAX_USERLAND_KEY_LEN);
-        Ok(Table::new(&self, id, anchor))
+    fn build_table_stats(
+        &self, files: &[LiveFile], name: String, id: TableId,
+    ) -> Result<TableStats, Error> {
+        let anchor = build_table_anchor(id);
+        let (approximate_size, approximate_num_keys) = estimate_range(files, &id, &anchor);
+        let table = Table::new(self, id, anchor);
+        let mut cursor = table.cursor();
+        cursor.seek_to_first();
+        let first_key = cursor.key().map(Bytes::copy_from_slice);
+        cursor.seek_to_last();
+        let last_key = cursor.key().map(Bytes::copy_from_slice);
+        cursor.status()?;
+        Ok(TableStats { name, id, approximate_size, approximate_num_keys, first_key, last_key })
+    }
+
+    /// Writes a batch and wakes the subscriptions, every write goes through here or `put_raw`
+    /// and `delete_raw`.
+    #[inline]
+    pub(in crate) fn write_raw(&self, batch: RawBatch) -> Result<(), Error> {
+        {
+            let _guard = self.lock_write();
+            self.inner.write(batch.into_inner())?;
+        }
+        self.write_signal.notify();
+        self.trim_wal_periodically();
+        Ok(())
+    }
+
+    #[inline]
+    pub(in crate) fn put_raw<K, V>(&self, key: K, value: V) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>, {
+        {
+            let _guard = self.lock_write();
+            self.inner.put(key, value)?;
+        }
+        self.write_signal.notify();
+        self.trim_wal_periodically();
+        Ok(())
+    }
+
+    #[inline]
+    pub(in crate) fn delete_raw<K: AsRef<[u8]>>(&self, key: K) -> Result<(), Error> {
+        {
+            let _guard = self.lock_write();
+            self.inner.delete(key)?;
+        }
+        self.write_signal.notify();
+        self.trim_wal_periodically();
+        Ok(())
+    }
+
+    /// Blocks the writes until the guard is dropped.
+    #[inline]
+    pub(in crate) fn block_writes(&self) -> RwLockWriteGuard<'_, ()> {
+        // The lock guards no data, so a panic while holding it leaves nothing inconsistent.
+        self.write_lock.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    #[inline]
+    fn lock_write(&self) -> RwLockReadGuard<'_, ()> {
+        self.write_lock.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    #[inline]
+    fn lock_catalog(&self) -> MutexGuard<'_, ()> {
+        // The lock guards no data, so a panic while holding it leaves nothing inconsistent.
+        self.catalog_lock.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Allocates an id and registers it under `name` in a single write, the caller must hold the
+    /// catalog lock.
+    fn create_table(&self, name: &str) -> Result<Table<'_>, Error> {
+        let mut batch = RawBatch::default();
+        let id = self.generate_next_table_id(&mut batch)?;
+        register_table(&mut batch, name, id);
+        let meta = TableMeta { created_at: Some(Millis::now()), ..TableMeta::new() };
+        batch.put(build_table_meta_table_inner_key(id), encode_value(&meta)?);
+        self.write_raw(batch)?;
+        Ok(Table::new(self, id, build_table_anchor(id)))
     }
 
-    fn generate_next_table_id(&self) -> Result<TableId, Error> {
+    /// Picks the id of the next table and records its allocation in `batch`. Ids of destroyed
+    /// tables are reused first, bumping their generation.
+    fn generate_next_table_id(&self, batch: &mut RawBatch) -> Result<TableId, Error> {
+        if let Some(id) = self.get_first_free_table_id()? {
+            let generation = self.get_table_generation(id)? + 1;
+            batch.delete(build_info_table_item_inner_key(FREE_TABLE_ID_ITEM_ID, id));
+            batch.put(
+                build_info_table_item_inner_key(TABLE_GENERATION_ITEM_ID, id),
+                generation.to_be_bytes(),
+            );
+            return Ok(id);
+        }
         let seed_key = build_info_table_inner_key(SEED_ITEM_ID);
         if let Some(seed_value) = self.inner.get(&seed_key)? {
             let seed_value = u8s_to_u32(seed_value.as_ref());
             if u32_to_table_id(seed_value) >= MAX_USERLAND_TABLE_ID {
-                panic!("Exceeded limit: {:?}", MAX_USERLAND_TABLE_ID)
+                return Err(Error::TableIdExhausted);
             }
-            let seed_value = seed_value + 1;
-            let next_id = u32_to_table_id(seed_value);
-            self.inner.put(&seed_key, next_id)?;
+            let next_id = u32_to_table_id(seed_value + 1);
+            batch.put(seed_key, next_id);
             Ok(next_id)
         } else {
-            // Double write to fixed raw get_updates_since bug.
-            self.inner.put(&seed_key, MIN_USERLAND_TABLE_ID)?;
-            self.inner.put(&seed_key, MIN_USERLAND_TABLE_ID)?;
+            // Write the seed ahead of the first table to work around the raw get_updates_since
+            // bug, which skips the first batch. The seed is one below the first id, so nothing is
+            // leaked if we crash before the first table is registered.
+            let seed_value = table_id_to_u32(MIN_USERLAND_TABLE_ID) - 1;
+            self.put_raw(&seed_key, u32_to_table_id(seed_value))?;
+            batch.put(seed_key, MIN_USERLAND_TABLE_ID);
             Ok(MIN_USERLAND_TABLE_ID)
         }
     }
 
-    #[inline]
-    fn register_table<K: AsRef<[u8]>>(
-        &self, name_to_id_table_inner_key: K, id: TableId, id_to_name_table_inner_key: K,
-        name: &str,
-    ) -> Result<(), Error> {
-        let mut batch = WriteBatch::default();
-        batch.put(name_to_id_table_inner_key, id);
-        batch.put(id_to_name_table_inner_key, name);
-        self.inner.write(batch)
+    fn get_first_free_table_id(&self) -> Result<Option<TableId>, Error> {
+        let mut opts = ReadOptions::default();
+        opts.set_total_order_seek(true);
+        opts.set_iterate_lower_bound(build_info_table_inner_key(FREE_TABLE_ID_ITEM_ID).to_vec());
+        opts.set_iterate_upper_bound(build_info_table_inner_key(TABLE_GENERATION_ITEM_ID).to_vec());
+        let mut iter = self.inner.raw_iterator_opt(opts);
+        iter.seek_to_first();
+        if let Some(key) = iter.key() {
+            return Ok(Some(u8s_to_table_id(&key[key.len() - TABLE_ID_LEN..])));
+        }
+        iter.status()?;
+        Ok(None)
+    }
+}
+
+#[inline]
+fn register_table(batch: &mut RawBatch, name: &str, id: TableId) {
+    batch.put(build_name_to_id_table_inner_key(name), id);
+    batch.put(build_id_to_name_table_inner_key(id), name);
+}
+
+/// Unregisters the id of a table, clears its entries and metadata and puts the id on the free list.
+#[inline]
+fn release_table(batch: &mut RawBatch, id: TableId) {
+    batch.delete(build_id_to_name_table_inner_key(id));
+    batch.delete(build_table_meta_table_inner_key(id));
+    batch.put(build_info_table_item_inner_key(FREE_TABLE_ID_ITEM_ID, id), []);
+    clear_table(batch, id);
+}
+
+/// Deletes every entry of a table, recording the range for the change feed.
+#[inline]
+pub(in crate) fn clear_table(batch: &mut RawBatch, id: TableId) {
+    let anchor = build_table_anchor(id);
+    batch.delete_range(id, anchor);
+}
+
+/// Turns `names` back into the names before a batch of the shapes `register_table` and
+/// `release_table` write. A renamed table takes back the name the batch dropped, or the one its
+/// swap partner got, and a released table the name left over.
+fn undo_catalog_changes(
+    names: &mut HashMap<TableId, String>, batch: &UpdateBatch,
+) -> Result<(), Error> {
+    let seed_key = build_info_table_inner_key(SEED_ITEM_ID);
+    let mut created = HashSet::new();
+    let mut dropped = vec![];
+    let mut registered = vec![];
+    let mut released = vec![];
+    for update in &batch.updates {
+        match update {
+            // A new id is allocated from the seed, a reused one gets its generation bumped.
+            Update::Put { key, value } if *key == seed_key && value.len() == TABLE_ID_LEN => {
+                created.insert(u8s_to_table_id(value));
+            }
+            Update::Put { key, value } if is_id_to_name_table_inner_key(key) => {
+                registered.push((u8s_to_table_id(extract_key(key)), decode_table_name(value)?));
+            }
+            Update::Put { key, .. } => created.extend(extract_table_generation_id(key)),
+            Update::Delete { key } if is_id_to_name_table_inner_key(key) => {
+                released.push(u8s_to_table_id(extract_key(key)));
+            }
+            Update::Delete { key } if matches!(update.table_id(), Ok(NAME_TO_ID_TABLE_ID)) => {
+                dropped.push(decode_table_name(extract_key(key))?);
+            }
+            _ => {}
+        }
+    }
+    for id in &created {
+        names.remove(id);
+    }
+    registered.retain(|(id, _)| !created.contains(id));
+    let mut left = dropped;
+    left.extend(registered.iter().map(|(_, name)| name.clone()));
+    for (id, name) in registered {
+        match left.iter().position(|left_name| *left_name != name) {
+            Some(i) => names.insert(id, left.remove(i)),
+            None => names.remove(&id),
+        };
+    }
+    for id in released {
+        if !left.is_empty() {
+            names.insert(id, left.remove(0));
+        }
+    }
+    Ok(())
+}
+
+#[inline]
+fn decode_consumer_name(name: &[u8]) -> Result<String, Error> {
+    match std::str::from_utf8(name) {
+        Ok(name) => Ok(name.to_owned()),
+        Err(e) => Err(Error::corruption(format!("Invalid consumer name {:?}: {}", name, e))),
+    }
+}
+
+#[inline]
+fn decode_consumer_position(name: &str, value: &[u8]) -> Result<u64, Error> {
+    match value.len() {
+        8 => Ok(u8s_to_u64(value)),
+        _ => Err(Error::corruption(format!("Invalid position of consumer {}: {:?}", name, value))),
+    }
+}
+
+#[inline]
+pub(in crate) fn decode_table_generation(value: &[u8]) -> Result<u32, Error> {
+    match value.len() {
+        4 => Ok(u8s_to_u32(value)),
+        _ => Err(Error::corruption(format!("Invalid table generation {:?}", value))),
+    }
+}
+
+#[inline]
+pub(in crate) fn decode_table_name(name: &[u8]) -> Result<String, Error> {
+    match std::str::from_utf8(name) {
+        Ok(name) => Ok(name.to_owned()),
+        Err(e) => Err(Error::corruption(format!("Invalid table name {:?}: {}", name, e))),
     }
 }
 
@@ -174,6 +904,42 @@ fn test_new_table() {
     run_test("test_new_table", |db| assert!(db.new_table("huobi.btc.usdt.1min").is_ok()));
 }
 
+#[test]
+fn test_get_or_create_table() {
+    run_test("test_get_or_create_table", |db| {
+        let (table, created) = db.get_or_create_table("huobi.btc.usdt.1min").unwrap();
+        assert!(created);
+        let (same_table, created) = db.get_or_create_table("huobi.btc.usdt.1min").unwrap();
+        assert!(!created);
+        assert_eq!(same_table.id, table.id);
+    });
+}
+
+#[test]
+fn test_open_table() {
+    run_test("test_open_table", |db| {
+        assert!(db.open_table("huobi.btc.usdt.1min").unwrap().is_none());
+        assert!(db.get_table_id_by_name("huobi.btc.usdt.1min").unwrap().is_none());
+        let table = db.new_table("huobi.btc.usdt.1min").unwrap();
+        table.put(b"k111", b"v111").unwrap();
+        let opened = db.open_table("huobi.btc.usdt.1min").unwrap().unwrap();
+        assert_eq!(opened.get(b"k111").unwrap().unwrap(), b"v111");
+    });
+}
+
+#[test]
+fn test_table_by_id() {
+    run_test("test_table_by_id", |db| {
+        assert!(db.table_by_id(MIN_USERLAND_TABLE_ID).unwrap().is_none());
+        let table = db.new_table("huobi.btc.usdt.1min").unwrap();
+        table.put(b"k111", b"v111").unwrap();
+        let opened = db.table_by_id(table.id).unwrap().unwrap();
+        assert_eq!(opened.get(b"k111").unwrap().unwrap(), b"v111");
+        db.destroy_table("huobi.btc.usdt.1min").unwrap();
+        assert!(db.table_by_id(table.id).unwrap().is_none());
+    });
+}
+
 #[test]
 fn test_destroy_table() {
     run_test("test_destroy_table", |db| {
@@ -202,6 +968,37 @@ fn test_truncate_table() {
     });
 }
 
+#[test]
+fn test_destroy_table_with_long_keys() {
+    run_test("test_destroy_table_with_long_keys", |db| {
+        let name = "huobi.btc.usdt.1min";
+        let table = db.new_table(name).unwrap();
+        let next_table = db.new_table("huobi.btc.usdt.5min").unwrap();
+        table.put([255; 16], b"v111").unwrap();
+        next_table.put(b"k111", b"v111").unwrap();
+        db.destroy_table(name).unwrap();
+        assert!(table.get([255; 16]).unwrap().is_none());
+        assert!(next_table.get(b"k111").unwrap().is_some());
+    });
+}
+
+#[test]
+fn test_truncate_table_with_long_keys() {
+    run_test("test_truncate_table_with_long_keys", |db| {
+        let name = "huobi.btc.usdt.1min";
+        let table = db.new_table(name).unwrap();
+        let next_table = db.new_table("huobi.btc.usdt.5min").unwrap();
+        table.put([255; 16], b"v111").unwrap();
+        next_table.put(b"k111", b"v111").unwrap();
+        db.truncate_table(name).unwrap();
+        assert!(table.get([255; 16]).unwrap().is_none());
+        let mut cursor = table.cursor();
+        cursor.seek_to_first();
+        assert!(!cursor.is_valid());
+        assert!(next_table.get(b"k111").unwrap().is_some());
+    });
+}
+
 #[test]
 fn test_rename_table() {
     run_test("test_rename_table", |db| {
@@ -210,11 +1007,11 @@ fn test_rename_table() {
         let table = db.new_table(old_name).unwrap();
         assert!(db.rename_table(old_name, new_name).is_ok());
 
-        let old_name_to_id_table_inner_key = build_name_to_id_table_inner_key(&old_name);
+        let old_name_to_id_table_inner_key = build_name_to_id_table_inner_key(old_name);
         let id = table.db.inner.get(old_name_to_id_table_inner_key);
         assert!(id.unwrap().is_none());
 
-        let new_name_to_id_table_inner_key = build_name_to_id_table_inner_key(&new_name);
+        let new_name_to_id_table_inner_key = build_name_to_id_table_inner_key(new_name);
         let id = table.db.inner.get(new_name_to_id_table_inner_key);
         assert_eq!(id.unwrap().unwrap().as_ref(), table.id);
 
@@ -224,6 +1021,110 @@ fn test_rename_table() {
     });
 }
 
+#[test]
+fn test_rename_table_to_existing_name() {
+    run_test("test_rename_table_to_existing_name", |db| {
+        let table_1m = db.new_table("huobi.btc.usdt.1min").unwrap();
+        let table_5m = db.new_table("huobi.btc.usdt.5min").unwrap();
+        table_5m.put(b"k111", b"v111").unwrap();
+        let result = db.rename_table("huobi.btc.usdt.1min", "huobi.btc.usdt.5min");
+        assert!(matches!(result, Err(Error::TableAlreadyExists(_))));
+        assert_eq!(db.get_table_id_by_name("huobi.btc.usdt.1min").unwrap(), Some(table_1m.id));
+        assert_eq!(db.get_table_id_by_name("huobi.btc.usdt.5min").unwrap(), Some(table_5m.id));
+        assert!(table_5m.get(b"k111").unwrap().is_some());
+    });
+}
+
+#[test]
+fn test_missing_table() {
+    run_test("test_missing_table", |db| {
+        let name = "huobi.btc.usdt.1min";
+        assert!(matches!(db.destroy_table(name), Err(Error::TableNotFound(_))));
+        assert!(matches!(db.truncate_table(name), Err(Error::TableNotFound(_))));
+        assert!(matches!(db.rename_table(name, "x"), Err(Error::TableNotFound(_))));
+        assert!(matches!(db.rename_table_overwrite(name, "x"), Err(Error::TableNotFound(_))));
+        db.new_table("huobi.btc.usdt.5min").unwrap();
+        let result = db.swap_tables("huobi.btc.usdt.5min", name);
+        assert!(matches!(result, Err(Error::TableNotFound(_))));
+    });
+}
+
+#[test]
+fn test_rename_table_overwrite() {
+    run_test("test_rename_table_overwrite", |db| {
+        let table_1m = db.new_table("huobi.btc.usdt.1min").unwrap();
+        let table_5m = db.new_table("huobi.btc.usdt.5min").unwrap();
+        table_1m.put(b"k1", b"v1").unwrap();
+        table_5m.put(b"k5", b"v5").unwrap();
+        db.rename_table_overwrite("huobi.btc.usdt.1min", "huobi.btc.usdt.5min").unwrap();
+        assert!(db.get_table_id_by_name("huobi.btc.usdt.1min").unwrap().is_none());
+        assert_eq!(db.get_table_id_by_name("huobi.btc.usdt.5min").unwrap(), Some(table_1m.id));
+        assert!(db.get_table_name_by_id(table_5m.id).unwrap().is_none());
+        assert!(table_5m.get(b"k5").unwrap().is_none());
+        let table = db.new_table("huobi.btc.usdt.5min").unwrap();
+        assert_eq!(table.get(b"k1").unwrap().unwrap(), b"v1");
+        assert_eq!(db.get_tables().unwrap().len(), 1);
+    });
+}
+
+#[test]
+fn test_swap_tables() {
+    run_test("test_swap_tables", |db| {
+        let table_1m = db.new_table("huobi.btc.usdt.1min").unwrap();
+        let table_5m = db.new_table("huobi.btc.usdt.5min").unwrap();
+        table_1m.put(b"k", b"1min").unwrap();
+        table_5m.put(b"k", b"5min").unwrap();
+        let sn = db.get_latest_sn();
+        db.swap_tables("huobi.btc.usdt.1min", "huobi.btc.usdt.5min").unwrap();
+        assert_eq!(db.get_updates_since(sn).unwrap().count(), 1);
+        let table = db.new_table("huobi.btc.usdt.1min").unwrap();
+        assert_eq!(table.get(b"k").unwrap().unwrap(), b"5min");
+        let table = db.new_table("huobi.btc.usdt.5min").unwrap();
+        assert_eq!(table.get(b"k").unwrap().unwrap(), b"1min");
+        assert_eq!(db.get_table_name_by_id(table_1m.id).unwrap().unwrap(), "huobi.btc.usdt.5min");
+        assert_eq!(db.get_table_name_by_id(table_5m.id).unwrap().unwrap(), "huobi.btc.usdt.1min");
+    });
+}
+
+#[test]
+fn test_table_meta() {
+    run_test("test_table_meta", |db| {
+        let table = db.new_table("huobi.btc.usdt.1min").unwrap();
+        let created_at = db.get_table_meta("huobi.btc.usdt.1min").unwrap().unwrap().created_at;
+        assert!(created_at.is_some());
+        let mut meta = TableMeta {
+            description: Some("1 minute klines".to_owned()),
+            key_codec: Some("Secs".to_owned()),
+            retention: Some(std::time::Duration::from_secs(86400)),
+            ..TableMeta::new()
+        };
+        let sn = db.get_latest_sn();
+        db.set_table_meta("huobi.btc.usdt.1min", &meta).unwrap();
+        let batch = db.get_updates_since(sn).unwrap().next().unwrap().unwrap();
+        let meta_key = build_table_meta_table_inner_key(table.id);
+        assert!(matches!(
+            &batch.updates[..],
+            [crate::update::Update::Put { key, .. }] if *key == meta_key
+        ));
+        db.rename_table("huobi.btc.usdt.1min", "huobi.btc.usdt.5min").unwrap();
+        meta.created_at = created_at;
+        assert_eq!(db.get_table_meta("huobi.btc.usdt.5min").unwrap(), Some(meta.clone()));
+        let overwrite = TableMeta { created_at: Some(Millis(0)), ..TableMeta::new() };
+        db.set_table_meta("huobi.btc.usdt.5min", &overwrite).unwrap();
+        let stored = db.get_table_meta("huobi.btc.usdt.5min").unwrap().unwrap();
+        assert_eq!(stored.created_at, created_at);
+        db.destroy_table("huobi.btc.usdt.5min").unwrap();
+        let result = db.get_table_meta("huobi.btc.usdt.5min");
+        assert!(matches!(result, Err(Error::TableNotFound(_))));
+        let result = db.set_table_meta("huobi.btc.usdt.5min", &TableMeta::new());
+        assert!(matches!(result, Err(Error::TableNotFound(_))));
+        let table = db.new_table("huobi.btc.usdt.15min").unwrap();
+        assert_eq!(table.id, MIN_USERLAND_TABLE_ID);
+        let meta = db.get_table_meta("huobi.btc.usdt.15min").unwrap().unwrap();
+        assert_eq!(meta, TableMeta { created_at: meta.created_at, ..TableMeta::new() });
+    });
+}
+
 #[test]
 fn test_get_tables() {
     run_test("test_get_tables", |db| {
@@ -236,7 +1137,7 @@ fn test_get_tables() {
         let id0 = table_id_to_u32(table0.id);
         let id1 = table_id_to_u32(table1.id);
         let id2 = table_id_to_u32(table2.id);
-        let result = db.get_tables();
+        let result = db.get_tables().unwrap();
         assert_eq!(result, vec![(name0, id0), (name1, id1), (name2, id2)]);
     });
 }
@@ -311,9 +1212,18 @@ fn test_get_updates_since() {
         let iter = db.get_updates_since(0).unwrap();
         let mut result = vec![];
         for ub in iter {
+            let mut ub = ub.unwrap();
+            // The creation times recorded in the metadata vary from run to run.
+            for update in &mut ub.updates {
+                if let Update::Put { key, value } = update {
+                    if key.starts_with(&TABLE_META_TABLE_ID) {
+                        *value = Bytes::new();
+                    }
+                }
+            }
             result.push(ub);
         }
-        assert_eq!(format!("{:?}", result), "[[Put {key:b\"\\0\\0\\0\\0\\0\\0\", value:b\"\\0\\0\\x04\\0\"}]@2, [Put {key:b\"\\0\\0\\0\\x01huobi.btc.usdt.1m\", value:b\"\\0\\0\\x04\\0\"}, Put {key:b\"\\0\\0\\0\\x02\\0\\0\\x04\\0\", value:b\"huobi.btc.usdt.1m\"}]@3, [Put {key:b\"\\0\\0\\0\\0\\0\\0\", value:b\"\\0\\0\\x04\\x01\"}]@5, [Put {key:b\"\\0\\0\\0\\x01huobi.btc.usdt.3m\", value:b\"\\0\\0\\x04\\x01\"}, Put {key:b\"\\0\\0\\0\\x02\\0\\0\\x04\\x01\", value:b\"huobi.btc.usdt.3m\"}]@6, [Delete {key:b\"\\0\\0\\0\\x01huobi.btc.usdt.3m\"}, Delete {key:b\"\\0\\0\\0\\x02\\0\\0\\x04\\x01\"}, DeleteRange {from_key:b\"\\0\\0\\x04\\x01\", to_key:b\"\\0\\0\\x04\\x01\\xff\\xff\\xff\\xff\\xff\"}]@8, [Put {key:b\"\\0\\0\\x04\\x01k111\", value:b\"v111\"}]@12, [Delete {key:b\"\\0\\0\\x04\\x01k111\"}]@13, [Put {key:b\"\\0\\0\\x04\\x01k112\", value:b\"v112\"}, Delete {key:b\"\\0\\0\\x04\\x01k111\"}, DeleteRange {from_key:b\"k111\", to_key:b\"k112\"}]@14]");
+        assert_eq!(format!("{:?}", result), "[[Put {key:b\"\\0\\0\\0\\0\\0\\0\", value:b\"\\0\\0\\x04\\0\"}, Put {key:b\"\\0\\0\\0\\x01huobi.btc.usdt.1m\", value:b\"\\0\\0\\x04\\0\"}, Put {key:b\"\\0\\0\\0\\x02\\0\\0\\x04\\0\", value:b\"huobi.btc.usdt.1m\"}, Put {key:b\"\\0\\0\\0\\x04\\0\\0\\x04\\0\", value:b\"\"}]@2, [Put {key:b\"\\0\\0\\0\\0\\0\\0\", value:b\"\\0\\0\\x04\\x01\"}, Put {key:b\"\\0\\0\\0\\x01huobi.btc.usdt.3m\", value:b\"\\0\\0\\x04\\x01\"}, Put {key:b\"\\0\\0\\0\\x02\\0\\0\\x04\\x01\", value:b\"huobi.btc.usdt.3m\"}, Put {key:b\"\\0\\0\\0\\x04\\0\\0\\x04\\x01\", value:b\"\"}]@6, [Delete {key:b\"\\0\\0\\0\\x01huobi.btc.usdt.3m\"}, Delete {key:b\"\\0\\0\\0\\x02\\0\\0\\x04\\x01\"}, Delete {key:b\"\\0\\0\\0\\x04\\0\\0\\x04\\x01\"}, Put {key:b\"\\0\\0\\0\\0\\0\\x01\\0\\0\\x04\\x01\", value:b\"\"}, DeleteRange {from_key:b\"\\0\\0\\x04\\x01\", to_key:b\"\\0\\0\\x04\\x02\"}]@10, [Put {key:b\"\\0\\0\\x04\\x01k111\", value:b\"v111\"}]@16, [Delete {key:b\"\\0\\0\\x04\\x01k111\"}]@17, [Put {key:b\"\\0\\0\\x04\\x01k112\", value:b\"v112\"}, Delete {key:b\"\\0\\0\\x04\\x01k111\"}, DeleteRange {from_key:b\"\\0\\0\\x04\\x01k111\", to_key:b\"\\0\\0\\x04\\x01k112\"}]@18]");
     })
 }
 
@@ -330,31 +1240,213 @@ fn test_create_table() {
 #[test]
 fn test_generate_next_table_id() {
     run_test("test_generate_next_table_id", |db| {
-        let id = db.generate_next_table_id().unwrap();
+        let id = db.generate_next_table_id(&mut RawBatch::default()).unwrap();
         assert_eq!(id, MIN_USERLAND_TABLE_ID);
+        let id = db.generate_next_table_id(&mut RawBatch::default()).unwrap();
+        assert_eq!(id, MIN_USERLAND_TABLE_ID);
+        db.create_table("huobi.btc.usdt.1m").unwrap();
+        let id = db.generate_next_table_id(&mut RawBatch::default()).unwrap();
+        assert_eq!(id, [0, 0, 4, 1]);
+    })
+}
+
+#[test]
+fn test_reuse_table_id() {
+    run_test("test_reuse_table_id", |db| {
+        let table_1m = db.new_table("huobi.btc.usdt.1m").unwrap();
+        let table_5m = db.new_table("huobi.btc.usdt.5m").unwrap();
+        table_1m.put(b"k111", b"v111").unwrap();
+        assert_eq!(db.get_table_generation(table_1m.id).unwrap(), 0);
+        db.destroy_table("huobi.btc.usdt.1m").unwrap();
+        let sn = db.get_latest_sn();
+        let table_15m = db.new_table("huobi.btc.usdt.15m").unwrap();
+        assert_eq!(table_15m.id, table_1m.id);
+        assert_eq!(db.get_table_generation(table_15m.id).unwrap(), 1);
+        assert!(table_15m.get(b"k111").unwrap().is_none());
+        let batch = db.get_updates_since(sn).unwrap().next().unwrap().unwrap();
+        let generation_key = build_info_table_item_inner_key(TABLE_GENERATION_ITEM_ID, table_1m.id);
+        assert!(batch.updates.iter().any(|update| matches!(
+            update,
+            crate::update::Update::Put { key, value } if *key == generation_key && value[..] == [0, 0, 0, 1]
+        )));
+        let table_30m = db.new_table("huobi.btc.usdt.30m").unwrap();
+        assert_eq!(table_id_to_u32(table_30m.id), table_id_to_u32(table_5m.id) + 1);
+    })
+}
+
+#[test]
+fn test_reuse_table_id_after_exhausted() {
+    run_test("test_reuse_table_id_after_exhausted", |db| {
+        db.new_table("huobi.btc.usdt.1m").unwrap();
+        db.inner.put(build_info_table_inner_key(SEED_ITEM_ID), MAX_USERLAND_TABLE_ID).unwrap();
+        assert!(matches!(db.new_table("huobi.btc.usdt.5m"), Err(Error::TableIdExhausted)));
+        db.destroy_table("huobi.btc.usdt.1m").unwrap();
+        assert_eq!(db.new_table("huobi.btc.usdt.5m").unwrap().id, MIN_USERLAND_TABLE_ID);
+        db.rename_table_overwrite("huobi.btc.usdt.5m", "huobi.btc.usdt.5m").unwrap();
+        assert!(matches!(db.new_table("huobi.btc.usdt.15m"), Err(Error::TableIdExhausted)));
     })
 }
 
 #[test]
-fn test_register_table() {
-    run_test("test_register_table", |db| {
+fn test_table_id_exhausted() {
+    run_test("test_table_id_exhausted", |db| {
+        db.inner.put(build_info_table_inner_key(SEED_ITEM_ID), MAX_USERLAND_TABLE_ID).unwrap();
+        assert!(matches!(db.new_table("huobi.btc.usdt.1m"), Err(Error::TableIdExhausted)));
+        assert!(db.get_table_id_by_name("huobi.btc.usdt.1m").unwrap().is_none());
+    })
+}
+
+#[test]
+fn test_corrupted_table_name() {
+    run_test("test_corrupted_table_name", |db| {
+        db.inner.put(build_id_to_name_table_inner_key(MIN_USERLAND_TABLE_ID), [255, 255]).unwrap();
+        let result = db.get_table_name_by_id(MIN_USERLAND_TABLE_ID);
+        assert!(matches!(result, Err(Error::Corruption(_))));
+        assert!(matches!(db.get_tables(), Err(Error::Corruption(_))));
+    })
+}
+
+#[test]
+fn test_create_table_in_one_batch() {
+    run_test("test_create_table_in_one_batch", |db| {
         let name = "huobi.btc.usdt.1m";
-        let name_clone = name.clone();
-        let table = db.new_table(name).unwrap();
-        let name_to_id_table_inner_key = build_name_to_id_table_inner_key(&name_clone);
-        let id_to_name_table_inner_key = build_id_to_name_table_inner_key(MIN_USERLAND_TABLE_ID);
-        let result = db.register_table(
-            &name_to_id_table_inner_key,
-            MIN_USERLAND_TABLE_ID,
-            &id_to_name_table_inner_key,
-            &name_clone,
-        );
-        assert!(result.is_ok());
+        db.new_table(name).unwrap();
+        let sn = db.get_latest_sn();
+        let table = db.new_table("huobi.btc.usdt.5m").unwrap();
+        let batches: Vec<_> = db.get_updates_since(sn).unwrap().map(Result::unwrap).collect();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].updates.len(), 4);
+
+        let id = table.db.inner.get(build_info_table_inner_key(SEED_ITEM_ID));
+        assert_eq!(id.unwrap().unwrap().as_ref(), table.id);
 
-        let id = table.db.inner.get(name_to_id_table_inner_key);
+        let id = table.db.inner.get(build_name_to_id_table_inner_key(name));
         assert_eq!(id.unwrap().unwrap().as_ref(), [0, 0, 4, 0]);
 
-        let name = table.db.inner.get(id_to_name_table_inner_key);
+        let name = table.db.inner.get(build_id_to_name_table_inner_key(MIN_USERLAND_TABLE_ID));
         assert_eq!(std::str::from_utf8(&name.unwrap().unwrap()).unwrap(), "huobi.btc.usdt.1m");
     })
 }
+
+#[test]
+fn test_create_tables_concurrently() {
+    run_test("test_create_tables_concurrently", |db| {
+        let db = Arc::new(db);
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let db = db.clone();
+                std::thread::spawn(move || {
+                    (0..32)
+                        .map(|j| db.new_table(&format!("huobi.{}.usdt.{}m", i, j)).unwrap().id)
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let mut ids: Vec<TableId> =
+            handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), 8 * 32);
+        assert_eq!(db.get_tables().unwrap().len(), 8 * 32);
+        let next_id = table_id_to_u32(MIN_USERLAND_TABLE_ID) + 8 * 32;
+        let id = db.generate_next_table_id(&mut RawBatch::default()).unwrap();
+        assert_eq!(id, u32_to_table_id(next_id));
+    })
+}
+
+#[test]
+fn test_new_table_concurrently() {
+    run_test("test_new_table_concurrently", |db| {
+        let db = Arc::new(db);
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let db = db.clone();
+                std::thread::spawn(move || db.new_table("huobi.btc.usdt.1m").unwrap().id)
+            })
+            .collect();
+        let ids: Vec<TableId> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+        assert!(ids.iter().all(|id| *id == MIN_USERLAND_TABLE_ID));
+        assert_eq!(db.get_tables().unwrap().len(), 1);
+    })
+}
+
+#[cfg(test)]
+pub(in crate) fn assert_follows(leader: &Db, follower: &Db) {
+    let applied_sn_key = build_info_table_inner_key(APPLIED_SN_ITEM_ID);
+    let entries = |db: &Db| {
+        db.inner
+            .iterator(rocksdb::IteratorMode::Start)
+            .filter(|(key, _)| key[..] != applied_sn_key[..])
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(entries(leader), entries(follower));
+}
+
+#[test]
+fn test_apply() {
+    run_test("test_apply_leader", |leader| {
+        run_test("test_apply_follower", |follower| {
+            let table = leader.new_table("huobi.btc.usdt.1m").unwrap();
+            table.put(b"k1", b"v1").unwrap();
+            table.put(b"k2", b"v2").unwrap();
+            let mut batch = table.batch();
+            batch.delete_range(b"k1", b"k9");
+            batch.put(b"k2", b"v3");
+            table.write(batch).unwrap();
+            leader.new_table("huobi.btc.usdt.5m").unwrap().put(b"k1", b"v1").unwrap();
+            leader.rename_table_overwrite("huobi.btc.usdt.1m", "huobi.btc.usdt.5m").unwrap();
+            let position = leader.register_consumer("indexer").unwrap();
+            leader.commit("indexer", position).unwrap();
+            assert!(follower.get_applied_sn().unwrap().is_none());
+            for batch in leader.get_updates_since(0).unwrap() {
+                follower.apply(&batch.unwrap()).unwrap();
+            }
+            assert_follows(&leader, &follower);
+            let applied_sn = follower.get_applied_sn().unwrap().unwrap();
+            leader.new_table("huobi.btc.usdt.15m").unwrap().put(b"k1", b"v1").unwrap();
+            leader.destroy_table("huobi.btc.usdt.5m").unwrap();
+            let batches: Vec<UpdateBatch> =
+                leader.get_updates_since(applied_sn).unwrap().map(Result::unwrap).collect();
+            for batch in &batches {
+                follower.apply(batch).unwrap();
+            }
+            assert_follows(&leader, &follower);
+            let applied_sn = follower.get_applied_sn().unwrap().unwrap();
+            assert_eq!(applied_sn, batches.last().unwrap().sn);
+            follower.apply(&batches[0]).unwrap();
+            assert_eq!(follower.get_applied_sn().unwrap().unwrap(), applied_sn);
+            let table = follower.open_table("huobi.btc.usdt.15m").unwrap().unwrap();
+            assert_eq!(table.get(b"k1").unwrap().unwrap(), b"v1");
+        })
+    })
+}
+
+#[test]
+fn test_get_table_names_at() {
+    run_test("test_get_table_names_at", |db| {
+        let current_names = || -> HashMap<TableId, String> {
+            db.tables().map(|entry| entry.map(|(name, id)| (id, name)).unwrap()).collect()
+        };
+        db.new_table("huobi.btc.usdt.1m").unwrap();
+        let mut history = vec![(db.get_latest_sn(), current_names())];
+        let changes: Vec<Box<dyn Fn()>> = vec![
+            Box::new(|| drop(db.new_table("huobi.eth.usdt.1m").unwrap())),
+            Box::new(|| drop(db.new_table("huobi.xrp.usdt.1m").unwrap())),
+            Box::new(|| db.rename_table("huobi.btc.usdt.1m", "huobi.btc.usdt.5m").unwrap()),
+            Box::new(|| db.swap_tables("huobi.eth.usdt.1m", "huobi.xrp.usdt.1m").unwrap()),
+            Box::new(|| {
+                db.rename_table_overwrite("huobi.btc.usdt.5m", "huobi.eth.usdt.1m").unwrap()
+            }),
+            Box::new(|| db.destroy_table("huobi.xrp.usdt.1m").unwrap()),
+            Box::new(|| drop(db.new_table("huobi.btc.usdt.15m").unwrap())),
+            Box::new(|| db.truncate_table("huobi.btc.usdt.15m").unwrap()),
+        ];
+        for change in changes {
+            change();
+            history.push((db.get_latest_sn(), current_names()));
+        }
+        for (sn, names) in history {
+            assert_eq!(db.get_table_names_at(sn).unwrap(), names);
+        }
+    })
+}
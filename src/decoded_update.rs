@@ -0,0 +1,244 @@
+use crate::consts::*;
+use crate::db::{decode_table_generation, decode_table_name, Db};
+use crate::types::*;
+use crate::update::Update;
+use crate::update_batch::UpdateBatch;
+use crate::update_iterator::UpdateIterator;
+use crate::utils::*;
+use crate::Error;
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// An update of a userland table, keyed by user keys.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableUpdate {
+    pub table_id: TableId,
+    /// The generation of the table id when the batch was written, see `Db::get_table_generation`.
+    pub generation: u32,
+    /// The name of the table when the batch was written, or its current name if the feed started
+    /// after the table was created. `None` if the table no longer exists or its id was reused.
+    pub table_name: Option<Arc<str>>,
+    pub op: TableOp,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TableOp {
+    Put {
+        key: Bytes,
+        value: Bytes,
+    },
+    Delete {
+        key: Bytes,
+    },
+    /// Deletes `[from_key, to_key)`, or everything from `from_key` on when `to_key` is `None`.
+    DeleteRange {
+        from_key: Bytes,
+        to_key: Option<Bytes>,
+    },
+}
+
+/// A write batch split into the updates of userland tables and the writes to system tables,
+/// which stay raw.
+#[derive(Debug)]
+pub struct DecodedBatch {
+    pub sn: u64,
+    pub updates: Vec<TableUpdate>,
+    pub system_updates: Vec<Update>,
+}
+
+pub struct DecodedUpdateIterator<'a> {
+    db: &'a Db,
+    inner: UpdateIterator,
+    // Follows the id-to-name table through the feed, `None` for destroyed tables.
+    names: HashMap<TableId, Option<Arc<str>>>,
+    // Follows the generation puts through the feed, starting from the generations as of its start.
+    generations: HashMap<TableId, u32>,
+}
+
+impl<'a> DecodedUpdateIterator<'a> {
+    #[inline]
+    pub(in crate) fn new(
+        db: &'a Db, inner: UpdateIterator, generations: HashMap<TableId, u32>,
+    ) -> Self {
+        DecodedUpdateIterator { db, inner, names: HashMap::new(), generations }
+    }
+
+    fn decode(&mut self, batch: UpdateBatch) -> Result<DecodedBatch, Error> {
+        let mut registered = HashMap::new();
+        let mut released = vec![];
+        for update in &batch.updates {
+            match update {
+                Update::Put { key, value } if is_id_to_name_table_inner_key(key) => {
+                    registered.insert(
+                        u8s_to_table_id(extract_key(key)),
+                        decode_table_name(value)?.into(),
+                    );
+                }
+                Update::Delete { key } if is_id_to_name_table_inner_key(key) => {
+                    released.push(u8s_to_table_id(extract_key(key)));
+                }
+                Update::Put { key, value } => {
+                    if let Some(id) = extract_table_generation_id(key) {
+                        self.generations.insert(id, decode_table_generation(value)?);
+                    }
+                }
+                _ => {}
+            }
+        }
+        let mut decoded = DecodedBatch { sn: batch.sn, updates: vec![], system_updates: vec![] };
+        for update in batch.updates {
+            let table_id = update.table_id()?;
+            if !is_userland_table_id(table_id) {
+                decoded.system_updates.push(update);
+                continue;
+            }
+            let generation = self.generations.get(&table_id).copied().unwrap_or_default();
+            let table_name = self.resolve_name(table_id, generation, &registered)?;
+            let op = match update {
+                Update::Put { key, value } => {
+                    TableOp::Put { key: key.slice(TABLE_ID_LEN..), value }
+                }
+                Update::Delete { key } => TableOp::Delete { key: key.slice(TABLE_ID_LEN..) },
+                Update::DeleteRange { from_key, to_key } => {
+                    let to_key =
+                        if to_key.len() >= TABLE_ID_LEN && extract_table_id(&to_key) == table_id {
+                            Some(to_key.slice(TABLE_ID_LEN..))
+                        } else {
+                            None
+                        };
+                    TableOp::DeleteRange { from_key: from_key.slice(TABLE_ID_LEN..), to_key }
+                }
+            };
+            decoded.updates.push(TableUpdate { table_id, generation, table_name, op });
+        }
+        for id in released {
+            self.names.insert(id, None);
+        }
+        for (id, name) in registered {
+            self.names.insert(id, Some(name));
+        }
+        Ok(decoded)
+    }
+
+    fn resolve_name(
+        &mut self, table_id: TableId, generation: u32, registered: &HashMap<TableId, Arc<str>>,
+    ) -> Result<Option<Arc<str>>, Error> {
+        if let Some(name) = self.names.get(&table_id) {
+            return Ok(name.clone());
+        }
+        if let Some(name) = registered.get(&table_id) {
+            return Ok(Some(name.clone()));
+        }
+        let name: Option<Arc<str>> =
+            self.db.get_table_name_by_generation(table_id, generation)?.map(Into::into);
+        self.names.insert(table_id, name.clone());
+        Ok(name)
+    }
+}
+
+impl<'a> Iterator for DecodedUpdateIterator<'a> {
+    type Item = Result<DecodedBatch, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let batch = self.inner.next()?;
+        Some(batch.and_then(|batch| self.decode(batch)))
+    }
+}
+
+#[test]
+fn test_decoded_updates() {
+    run_test("test_decoded_updates", |db| {
+        let sn = db.get_latest_sn();
+        let table = db.new_table("huobi.btc.usdt.1m").unwrap();
+        table.put(b"k1", b"v1").unwrap();
+        let mut batch = table.batch();
+        batch.put(b"k2", b"v2");
+        batch.delete(b"k1");
+        batch.delete_range(b"k3", b"k5");
+        table.write(batch).unwrap();
+        db.rename_table("huobi.btc.usdt.1m", "huobi.btc.usdt.5m").unwrap();
+        table.put(b"k6", b"v6").unwrap();
+        db.destroy_table("huobi.btc.usdt.5m").unwrap();
+        let batches: Vec<DecodedBatch> =
+            db.get_decoded_updates_since(sn).unwrap().map(Result::unwrap).collect();
+        assert_eq!(batches.len(), 6);
+        assert!(batches[0].updates.is_empty());
+        let batches = &batches[1..];
+        let name_1m: Option<Arc<str>> = Some("huobi.btc.usdt.1m".into());
+        let name_5m: Option<Arc<str>> = Some("huobi.btc.usdt.5m".into());
+        let update = |table_name: &Option<Arc<str>>, op| TableUpdate {
+            table_id: table.id(),
+            generation: 0,
+            table_name: table_name.clone(),
+            op,
+        };
+        assert_eq!(
+            batches[0].updates,
+            vec![update(&name_1m, TableOp::Put { key: "k1".into(), value: "v1".into() })]
+        );
+        assert!(batches[0].system_updates.is_empty());
+        assert_eq!(
+            batches[1].updates,
+            vec![
+                update(&name_1m, TableOp::Put { key: "k2".into(), value: "v2".into() }),
+                update(&name_1m, TableOp::Delete { key: "k1".into() }),
+                update(
+                    &name_1m,
+                    TableOp::DeleteRange { from_key: "k3".into(), to_key: Some("k5".into()) }
+                ),
+            ]
+        );
+        assert!(batches[2].updates.is_empty());
+        assert!(!batches[2].system_updates.is_empty());
+        assert_eq!(
+            batches[3].updates,
+            vec![update(&name_5m, TableOp::Put { key: "k6".into(), value: "v6".into() })]
+        );
+        assert_eq!(
+            batches[4].updates,
+            vec![update(&name_5m, TableOp::DeleteRange { from_key: Bytes::new(), to_key: None })]
+        );
+    })
+}
+
+#[test]
+fn test_decoded_updates_of_new_table() {
+    run_test("test_decoded_updates_of_new_table", |db| {
+        db.new_table("huobi.btc.usdt.1m").unwrap();
+        let sn = db.get_latest_sn();
+        let table = db.new_table("huobi.btc.usdt.5m").unwrap();
+        table.put(b"k1", b"v1").unwrap();
+        db.destroy_table("huobi.btc.usdt.5m").unwrap();
+        let batches: Vec<DecodedBatch> =
+            db.get_decoded_updates_since(sn).unwrap().map(Result::unwrap).collect();
+        assert_eq!(batches.len(), 3);
+        assert!(batches[0].updates.is_empty());
+        assert_eq!(batches[1].updates[0].table_name.as_deref(), Some("huobi.btc.usdt.5m"));
+    })
+}
+
+#[test]
+fn test_decoded_updates_of_reused_table_id() {
+    run_test("test_decoded_updates_of_reused_table_id", |db| {
+        let table = db.new_table("huobi.btc.usdt.1m").unwrap();
+        let sn = db.get_latest_sn();
+        table.put(b"k1", b"v1").unwrap();
+        db.destroy_table("huobi.btc.usdt.1m").unwrap();
+        let reused = db.new_table("huobi.btc.usdt.5m").unwrap();
+        assert_eq!(reused.id(), table.id());
+        reused.put(b"k1", b"v1").unwrap();
+        let updates: Vec<TableUpdate> = db
+            .get_decoded_updates_since(sn)
+            .unwrap()
+            .flat_map(|batch| batch.unwrap().updates)
+            .collect();
+        assert_eq!(updates.len(), 3);
+        assert_eq!((updates[0].generation, updates[0].table_name.as_deref()), (0, None));
+        assert_eq!((updates[1].generation, updates[1].table_name.as_deref()), (0, None));
+        assert_eq!(
+            (updates[2].generation, updates[2].table_name.as_deref()),
+            (1, Some("huobi.btc.usdt.5m"))
+        );
+    })
+}
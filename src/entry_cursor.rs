@@ -1,36 +1,37 @@
+#[cfg(test)]
+use crate::memcomparable::encode;
+use crate::memcomparable::{encode_prefix, Memcomparable};
+#[cfg(test)]
+use crate::timestamp::Secs;
+use crate::timestamp::Timestamp;
 use crate::types::*;
 use crate::utils::*;
-use bytes::Bytes;
+use crate::Error;
 use rocksdb::DBRawIterator;
 
 pub struct EntryCursor<'a> {
     inner: DBRawIterator<'a>,
     table_id: TableId,
-    anchor: &'a Bytes,
 }
 
 impl<'a> EntryCursor<'a> {
-    pub(in crate) fn new(inner: DBRawIterator<'a>, table_id: TableId, anchor: &'a Bytes) -> Self {
-        EntryCursor { inner, table_id, anchor }
+    pub(in crate) fn new(inner: DBRawIterator<'a>, table_id: TableId) -> Self {
+        EntryCursor { inner, table_id }
     }
 
     #[inline]
     pub fn is_valid(&self) -> bool {
-        if self.inner.valid() {
-            return true;
-        } else {
-            return false;
-        }
+        self.inner.valid()
     }
 
     #[inline]
     pub fn seek_to_first(&mut self) {
-        self.inner.seek(self.table_id)
+        self.inner.seek_to_first()
     }
 
     #[inline]
     pub fn seek_to_last(&mut self) {
-        self.inner.seek_for_prev(self.anchor);
+        self.inner.seek_to_last()
     }
 
     #[inline]
@@ -43,6 +44,22 @@ impl<'a> EntryCursor<'a> {
         self.inner.seek_for_prev(build_inner_key(self.table_id, key));
     }
 
+    #[inline]
+    pub fn seek_time<T: Timestamp>(&mut self, ts: T) {
+        self.seek(ts.to_key());
+    }
+
+    #[inline]
+    pub fn seek_time_for_prev<T: Timestamp>(&mut self, ts: T) {
+        self.seek_for_prev(ts.to_key());
+    }
+
+    /// Seeks to the first entry whose key starts with the encoded tuple prefix.
+    #[inline]
+    pub fn seek_prefix<P: Memcomparable>(&mut self, prefix: &P) {
+        self.seek(encode_prefix(prefix));
+    }
+
     #[inline]
     pub fn next(&mut self) {
         self.inner.next()
@@ -62,10 +79,22 @@ impl<'a> EntryCursor<'a> {
         }
     }
 
+    /// Decodes the current key as a timestamp, returns `None` if it isn't one.
+    #[inline]
+    pub fn time<T: Timestamp>(&self) -> Option<T> {
+        self.key().and_then(T::from_key)
+    }
+
     #[inline]
     pub fn value(&self) -> Option<&[u8]> {
         self.inner.value()
     }
+
+    /// Returns the error that invalidated the cursor, if any.
+    #[inline]
+    pub fn status(&self) -> Result<(), Error> {
+        Ok(self.inner.status()?)
+    }
 }
 
 #[test]
@@ -88,3 +117,66 @@ fn test_seek() {
         assert_eq!(k1, iter.key().unwrap());
     });
 }
+
+#[test]
+fn test_seek_time() {
+    run_test("test_seek_time", |db| {
+        let name = "huobi.btc.usdt.1m";
+        let table = db.new_table(name).unwrap();
+        assert!(table.put_at(Secs(60), b"v1").is_ok());
+        assert!(table.put_at(Secs(180), b"v2").is_ok());
+        let mut iter = table.cursor();
+        iter.seek_time(Secs(61));
+        assert!(iter.is_valid());
+        assert_eq!(iter.time(), Some(Secs(180)));
+        iter.seek_time_for_prev(Secs(179));
+        assert!(iter.is_valid());
+        assert_eq!(iter.time(), Some(Secs(60)));
+    });
+}
+
+#[test]
+fn test_seek_to_last_with_long_keys() {
+    run_test("test_seek_to_last_with_long_keys", |db| {
+        let table = db.new_table("huobi.btc.usdt.1m").unwrap();
+        let next_table = db.new_table("huobi.btc.usdt.5m").unwrap();
+        let long_key = [255; 16];
+        assert!(table.put(b"k1", b"v1").is_ok());
+        assert!(table.put(long_key, b"v2").is_ok());
+        assert!(next_table.put(b"k1", b"v3").is_ok());
+        let mut iter = table.cursor();
+        iter.seek_to_last();
+        assert!(iter.is_valid());
+        assert_eq!(&long_key[..], iter.key().unwrap());
+        iter.next();
+        assert!(!iter.is_valid());
+        let mut iter = next_table.cursor();
+        iter.seek_to_first();
+        assert_eq!(b"v3", iter.value().unwrap());
+        iter.prev();
+        assert!(!iter.is_valid());
+    });
+}
+
+#[test]
+fn test_seek_prefix() {
+    run_test("test_seek_prefix", |db| {
+        let name = "huobi.1m";
+        let table = db.new_table(name).unwrap();
+        let key = |quote: &str, ts: u64| encode(&("btc".to_owned(), quote.to_owned(), Secs(ts)));
+        assert!(table.put(key("usdt", 120), b"v1").is_ok());
+        assert!(table.put(key("usdt", 60), b"v2").is_ok());
+        assert!(table.put(key("usd", 180), b"v3").is_ok());
+        assert!(table.put(key("usdtt", 0), b"v4").is_ok());
+        let prefix = ("btc".to_owned(), "usdt".to_owned());
+        let encoded_prefix = encode_prefix(&prefix);
+        let mut iter = table.cursor();
+        iter.seek_prefix(&prefix);
+        let mut result = vec![];
+        while iter.is_valid() && iter.key().unwrap().starts_with(&encoded_prefix) {
+            result.push(iter.value().unwrap().to_vec());
+            iter.next();
+        }
+        assert_eq!(result, vec![b"v2".to_vec(), b"v1".to_vec()]);
+    });
+}
@@ -0,0 +1,116 @@
+use crate::utils::*;
+use crate::Error;
+use bytes::Bytes;
+use rocksdb::DBRawIterator;
+#[cfg(test)]
+use std::ops::Bound;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(in crate) enum Direction {
+    Forward,
+    Reverse,
+}
+
+/// Iterates over `(key, value)` pairs of a table within the iterate bounds of `inner`.
+pub struct EntryIterator<'a> {
+    inner: DBRawIterator<'a>,
+    direction: Direction,
+    started: bool,
+}
+
+impl<'a> EntryIterator<'a> {
+    pub(in crate) fn new(inner: DBRawIterator<'a>, direction: Direction) -> Self {
+        EntryIterator { inner, direction, started: false }
+    }
+
+    /// Returns the error that ended the iteration early, if any.
+    #[inline]
+    pub fn status(&self) -> Result<(), Error> {
+        Ok(self.inner.status()?)
+    }
+}
+
+impl<'a> Iterator for EntryIterator<'a> {
+    type Item = (Bytes, Bytes);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Once the iterator is exhausted or failed, moving it is undefined in rocksdb.
+        if self.started && !self.inner.valid() {
+            return None;
+        }
+        match (self.started, self.direction) {
+            (false, Direction::Forward) => self.inner.seek_to_first(),
+            (false, Direction::Reverse) => self.inner.seek_to_last(),
+            (true, Direction::Forward) => self.inner.next(),
+            (true, Direction::Reverse) => self.inner.prev(),
+        }
+        self.started = true;
+        if self.inner.valid() {
+            let key = Bytes::copy_from_slice(extract_key(self.inner.key()?));
+            let value = Bytes::copy_from_slice(self.inner.value()?);
+            Some((key, value))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+fn collect_keys(iter: EntryIterator) -> Vec<Bytes> {
+    iter.map(|(k, _)| k).collect()
+}
+
+#[test]
+fn test_iter() {
+    run_test("test_iter", |db| {
+        let table = db.new_table("huobi.btc.usdt.1m").unwrap();
+        let next_table = db.new_table("huobi.btc.usdt.5m").unwrap();
+        table.put(b"k2", b"v2").unwrap();
+        table.put(b"k1", b"v1").unwrap();
+        table.put([255; 8], b"v3").unwrap();
+        next_table.put(b"k0", b"v0").unwrap();
+        let result: Vec<(Bytes, Bytes)> = table.iter().collect();
+        assert_eq!(
+            result,
+            vec![
+                (Bytes::from_static(b"k1"), Bytes::from_static(b"v1")),
+                (Bytes::from_static(b"k2"), Bytes::from_static(b"v2")),
+                (Bytes::from_static(&[255; 8]), Bytes::from_static(b"v3")),
+            ]
+        );
+    });
+}
+
+#[test]
+fn test_range() {
+    run_test("test_range", |db| {
+        let table = db.new_table("huobi.btc.usdt.1m").unwrap();
+        for key in &[b"k1", b"k2", b"k3", b"k4"] {
+            table.put(key, b"v").unwrap();
+        }
+        assert_eq!(collect_keys(table.range("k2".."k4")), vec!["k2", "k3"]);
+        assert_eq!(collect_keys(table.range("k2"..="k4")), vec!["k2", "k3", "k4"]);
+        assert_eq!(collect_keys(table.range("k3"..)), vec!["k3", "k4"]);
+        assert_eq!(collect_keys(table.range(.."k2")), vec!["k1"]);
+        assert_eq!(collect_keys(table.range("k5"..)), Vec::<Bytes>::new());
+        let excluded = (Bound::Excluded("k1".to_owned()), Bound::Included("k3".to_owned()));
+        assert_eq!(collect_keys(table.range(excluded)), vec!["k2", "k3"]);
+    });
+}
+
+#[test]
+fn test_range_rev() {
+    run_test("test_range_rev", |db| {
+        let table = db.new_table("huobi.btc.usdt.1m").unwrap();
+        for key in &[b"k1", b"k2", b"k3", b"k4"] {
+            table.put(key, b"v").unwrap();
+        }
+        assert_eq!(collect_keys(table.range_rev("k2".."k4")), vec!["k3", "k2"]);
+        assert_eq!(collect_keys(table.range_rev("k2"..="k4")), vec!["k4", "k3", "k2"]);
+        assert_eq!(collect_keys(table.range_rev(..="k1")), vec!["k1"]);
+        let mut iter = table.range_rev(..="k1");
+        assert!(iter.next().is_some());
+        assert!(iter.next().is_none());
+        assert!(iter.next().is_none());
+    });
+}
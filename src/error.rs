@@ -0,0 +1,89 @@
+use crate::codec::CodecError;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+#[derive(Debug)]
+pub enum Error {
+    /// An error reported by rocksdb.
+    Storage(rocksdb::Error),
+    TableNotFound(String),
+    TableAlreadyExists(String),
+    ConsumerNotFound(String),
+    /// A consumer committed a position ahead of the latest write or behind its last one.
+    InvalidPosition(String),
+    /// Every userland table id is taken.
+    TableIdExhausted,
+    /// Data that seriesdb wrote could not be read back.
+    Corruption(String),
+    Codec(CodecError),
+    Io(std::io::Error),
+    /// A batch could not be encoded into a frame, see `wire::encode_batch`.
+    Encode(String),
+    /// The replication leader ended the connection with an error.
+    Replication(String),
+    /// The WAL no longer holds the update numbered `expected`, so the change feed would miss
+    /// some. `found` is the sequence number the WAL goes on from, if known.
+    WalGap {
+        expected: u64,
+        found: Option<u64>,
+    },
+}
+
+impl Error {
+    #[inline]
+    pub(in crate) fn corruption<M: Into<String>>(message: M) -> Self {
+        Error::Corruption(message.into())
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Error::Storage(e) => write!(f, "Storage error: {}", e),
+            Error::TableNotFound(name) => write!(f, "Table not found: {}", name),
+            Error::TableAlreadyExists(name) => write!(f, "Table already exists: {}", name),
+            Error::ConsumerNotFound(name) => write!(f, "Consumer not found: {}", name),
+            Error::InvalidPosition(message) => write!(f, "Invalid position: {}", message),
+            Error::TableIdExhausted => write!(f, "Table id exhausted"),
+            Error::Corruption(message) => write!(f, "Corruption: {}", message),
+            Error::Codec(e) => write!(f, "{}", e),
+            Error::Io(e) => write!(f, "IO error: {}", e),
+            Error::Encode(message) => write!(f, "Encode error: {}", message),
+            Error::Replication(message) => write!(f, "Replication error: {}", message),
+            Error::WalGap { expected, found: Some(found) } => {
+                write!(f, "WAL gap: expected {}, found {}", expected, found)
+            }
+            Error::WalGap { expected, found: None } => {
+                write!(f, "WAL gap: expected {}", expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Storage(e) => Some(e),
+            Error::Codec(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<rocksdb::Error> for Error {
+    fn from(e: rocksdb::Error) -> Self {
+        Error::Storage(e)
+    }
+}
+
+impl From<CodecError> for Error {
+    fn from(e: CodecError) -> Self {
+        Error::Codec(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
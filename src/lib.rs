@@ -1,14 +1,35 @@
 pub mod batch;
 pub mod batch_x;
+pub mod catalog;
+pub mod codec;
 mod consts;
+pub mod consumer;
+pub mod copy;
 pub mod db;
+pub mod decoded_update;
 pub mod entry_cursor;
+pub mod entry_iterator;
+pub mod error;
+pub mod memcomparable;
 pub mod options;
+pub mod owned_table;
+mod raw_batch;
+pub mod replication;
+pub mod snapshot;
+pub mod subscription;
 pub mod table;
+pub mod table_filter;
+pub mod table_meta;
+pub mod table_stats;
+pub mod timestamp;
+pub mod typed_table;
 pub mod types;
 pub mod update;
 pub mod update_batch;
 pub mod update_iterator;
 pub mod utils;
+mod wal;
+pub mod wire;
 
+pub use error::Error;
 pub use types::*;
@@ -0,0 +1,357 @@
+use crate::codec::CodecError;
+use crate::timestamp::*;
+use byteorder::{BigEndian, ByteOrder};
+use bytes::{BufMut, Bytes, BytesMut};
+
+const ESCAPE: u8 = 0;
+const ESCAPED_ZERO: u8 = 255;
+const TERMINATOR: u8 = 1;
+
+/// A value that can be part of a composite key.
+///
+/// Encodings are memcomparable: comparing the encoded bytes of two tuples gives the same
+/// result as comparing the tuples element by element. Integers and floats are fixed width,
+/// strings and byte strings escape `0x00` as `0x00 0xFF` and end with `0x00 0x01`, so a
+/// tuple prefix always encodes to a byte prefix of the full key.
+pub trait Memcomparable: Sized {
+    fn encode_to(&self, buf: &mut BytesMut);
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, CodecError>;
+}
+
+#[inline]
+pub fn encode<T: Memcomparable>(value: &T) -> Bytes {
+    let mut buf = BytesMut::new();
+    value.encode_to(&mut buf);
+    buf.freeze()
+}
+
+#[inline]
+pub fn decode<T: Memcomparable>(mut buf: &[u8]) -> Result<T, CodecError> {
+    let value = T::decode_from(&mut buf)?;
+    if !buf.is_empty() {
+        return Err(CodecError::new(format!("{} trailing bytes after key", buf.len())));
+    }
+    Ok(value)
+}
+
+/// Encodes the leading parts of a composite key, e.g. `("huobi", "btc")` for keys shaped
+/// like `("huobi", "btc", Secs(..))`. Seeking to it lands on the first key with that prefix.
+#[inline]
+pub fn encode_prefix<P: Memcomparable>(prefix: &P) -> Bytes {
+    encode(prefix)
+}
+
+/// Returns the smallest key that sorts after every key starting with `prefix`, or `None`
+/// if no such key exists (the prefix is empty or all `0xFF`).
+pub fn prefix_end(prefix: &[u8]) -> Option<Bytes> {
+    let len = prefix.iter().rposition(|b| *b != 255)?;
+    let mut end = BytesMut::from(&prefix[..=len]);
+    end[len] += 1;
+    Some(end.freeze())
+}
+
+#[inline]
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8], CodecError> {
+    if buf.len() < len {
+        return Err(CodecError::new(format!("Expected {} bytes, got {}", len, buf.len())));
+    }
+    let (head, tail) = buf.split_at(len);
+    *buf = tail;
+    Ok(head)
+}
+
+#[inline]
+fn encode_bytes(bytes: &[u8], buf: &mut BytesMut) {
+    for b in bytes {
+        if *b == ESCAPE {
+            buf.put_slice(&[ESCAPE, ESCAPED_ZERO]);
+        } else {
+            buf.put_u8(*b);
+        }
+    }
+    buf.put_slice(&[ESCAPE, TERMINATOR]);
+}
+
+fn decode_bytes(buf: &mut &[u8]) -> Result<Vec<u8>, CodecError> {
+    let mut result = Vec::new();
+    loop {
+        match take(buf, 1)?[0] {
+            ESCAPE => match take(buf, 1)?[0] {
+                ESCAPED_ZERO => result.push(ESCAPE),
+                TERMINATOR => return Ok(result),
+                b => {
+                    return Err(CodecError::new(format!(
+                        "Invalid escape sequence: 0x00 {:#04x}",
+                        b
+                    )))
+                }
+            },
+            b => result.push(b),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// scalar impls
+////////////////////////////////////////////////////////////////////////////////
+macro_rules! impl_memcomparable_for_uint {
+    ($ty:ty, $len:expr, $write:path, $read:path) => {
+        impl Memcomparable for $ty {
+            #[inline]
+            fn encode_to(&self, buf: &mut BytesMut) {
+                let mut bytes = [0; $len];
+                $write(&mut bytes, *self);
+                buf.put_slice(&bytes);
+            }
+
+            #[inline]
+            fn decode_from(buf: &mut &[u8]) -> Result<Self, CodecError> {
+                Ok($read(take(buf, $len)?))
+            }
+        }
+    };
+}
+
+impl_memcomparable_for_uint!(u16, 2, BigEndian::write_u16, BigEndian::read_u16);
+impl_memcomparable_for_uint!(u32, 4, BigEndian::write_u32, BigEndian::read_u32);
+impl_memcomparable_for_uint!(u64, 8, BigEndian::write_u64, BigEndian::read_u64);
+impl_memcomparable_for_uint!(u128, 16, BigEndian::write_u128, BigEndian::read_u128);
+
+impl Memcomparable for u8 {
+    #[inline]
+    fn encode_to(&self, buf: &mut BytesMut) {
+        buf.put_u8(*self);
+    }
+
+    #[inline]
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(take(buf, 1)?[0])
+    }
+}
+
+impl Memcomparable for bool {
+    #[inline]
+    fn encode_to(&self, buf: &mut BytesMut) {
+        buf.put_u8(*self as u8);
+    }
+
+    #[inline]
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        match take(buf, 1)?[0] {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => Err(CodecError::new(format!("Invalid bool: {:#04x}", b))),
+        }
+    }
+}
+
+// Flipping the sign bit moves negative numbers below positive ones.
+macro_rules! impl_memcomparable_for_int {
+    ($ty:ty, $uty:ty) => {
+        impl Memcomparable for $ty {
+            #[inline]
+            fn encode_to(&self, buf: &mut BytesMut) {
+                ((*self as $uty) ^ (1 << (<$uty>::BITS - 1))).encode_to(buf)
+            }
+
+            #[inline]
+            fn decode_from(buf: &mut &[u8]) -> Result<Self, CodecError> {
+                Ok((<$uty>::decode_from(buf)? ^ (1 << (<$uty>::BITS - 1))) as $ty)
+            }
+        }
+    };
+}
+
+impl_memcomparable_for_int!(i8, u8);
+impl_memcomparable_for_int!(i16, u16);
+impl_memcomparable_for_int!(i32, u32);
+impl_memcomparable_for_int!(i64, u64);
+
+// Positive floats get the sign bit set, negative floats have every bit inverted so that
+// larger magnitudes sort lower.
+macro_rules! impl_memcomparable_for_float {
+    ($ty:ty, $uty:ty) => {
+        impl Memcomparable for $ty {
+            #[inline]
+            fn encode_to(&self, buf: &mut BytesMut) {
+                let bits = self.to_bits();
+                let sign = 1 << (<$uty>::BITS - 1);
+                let bits = if bits & sign == 0 { bits | sign } else { !bits };
+                bits.encode_to(buf)
+            }
+
+            #[inline]
+            fn decode_from(buf: &mut &[u8]) -> Result<Self, CodecError> {
+                let bits = <$uty>::decode_from(buf)?;
+                let sign = 1 << (<$uty>::BITS - 1);
+                let bits = if bits & sign == 0 { !bits } else { bits & !sign };
+                Ok(<$ty>::from_bits(bits))
+            }
+        }
+    };
+}
+
+impl_memcomparable_for_float!(f32, u32);
+impl_memcomparable_for_float!(f64, u64);
+
+macro_rules! impl_memcomparable_for_timestamp {
+    ($ty:ident) => {
+        impl Memcomparable for $ty {
+            #[inline]
+            fn encode_to(&self, buf: &mut BytesMut) {
+                self.0.encode_to(buf)
+            }
+
+            #[inline]
+            fn decode_from(buf: &mut &[u8]) -> Result<Self, CodecError> {
+                Ok($ty(u64::decode_from(buf)?))
+            }
+        }
+    };
+}
+
+impl_memcomparable_for_timestamp!(Secs);
+impl_memcomparable_for_timestamp!(Millis);
+impl_memcomparable_for_timestamp!(Nanos);
+
+impl Memcomparable for String {
+    #[inline]
+    fn encode_to(&self, buf: &mut BytesMut) {
+        encode_bytes(self.as_bytes(), buf)
+    }
+
+    #[inline]
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        String::from_utf8(decode_bytes(buf)?).map_err(|e| CodecError::new(e.to_string()))
+    }
+}
+
+impl Memcomparable for Vec<u8> {
+    #[inline]
+    fn encode_to(&self, buf: &mut BytesMut) {
+        encode_bytes(self, buf)
+    }
+
+    #[inline]
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        decode_bytes(buf)
+    }
+}
+
+impl Memcomparable for Bytes {
+    #[inline]
+    fn encode_to(&self, buf: &mut BytesMut) {
+        encode_bytes(self, buf)
+    }
+
+    #[inline]
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(Bytes::from(decode_bytes(buf)?))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// tuple impls
+////////////////////////////////////////////////////////////////////////////////
+macro_rules! impl_memcomparable_for_tuple {
+    ($($name:ident $idx:tt),+) => {
+        impl<$($name: Memcomparable),+> Memcomparable for ($($name,)+) {
+            #[inline]
+            fn encode_to(&self, buf: &mut BytesMut) {
+                $(self.$idx.encode_to(buf);)+
+            }
+
+            #[inline]
+            fn decode_from(buf: &mut &[u8]) -> Result<Self, CodecError> {
+                Ok(($($name::decode_from(buf)?,)+))
+            }
+        }
+    };
+}
+
+impl_memcomparable_for_tuple!(A 0);
+impl_memcomparable_for_tuple!(A 0, B 1);
+impl_memcomparable_for_tuple!(A 0, B 1, C 2);
+impl_memcomparable_for_tuple!(A 0, B 1, C 2, D 3);
+impl_memcomparable_for_tuple!(A 0, B 1, C 2, D 3, E 4);
+impl_memcomparable_for_tuple!(A 0, B 1, C 2, D 3, E 4, F 5);
+
+////////////////////////////////////////////////////////////////////////////////
+/// test cases
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+fn assert_sorted<T: Memcomparable + PartialOrd + std::fmt::Debug>(values: &[T]) {
+    for pair in values.windows(2) {
+        assert!(pair[0] < pair[1], "{:?} >= {:?}", pair[0], pair[1]);
+        assert!(encode(&pair[0]) < encode(&pair[1]), "{:?} >= {:?}", pair[0], pair[1]);
+    }
+    for value in values {
+        assert_eq!(&decode::<T>(&encode(value)).unwrap(), value);
+    }
+}
+
+#[test]
+fn test_int_order() {
+    assert_sorted(&[0u8, 1, 255]);
+    assert_sorted(&[255u64, 256, 65536, u64::MAX]);
+    assert_sorted(&[0u128, 1, u128::from(u64::MAX) + 1, u128::MAX]);
+    assert_sorted(&[i8::MIN, -1, 0, 1, i8::MAX]);
+    assert_sorted(&[i64::MIN, -65536, -256, -255, -1, 0, 1, 255, 256, i64::MAX]);
+}
+
+#[test]
+fn test_float_order() {
+    assert_sorted(&[
+        f64::NEG_INFINITY,
+        f64::MIN,
+        -1.5,
+        -f64::MIN_POSITIVE,
+        0.0,
+        0.5,
+        1.5,
+        f64::MAX,
+    ]);
+    assert_sorted(&[-2.0f32, -1.0, 0.0, 1.0, f32::INFINITY]);
+}
+
+#[test]
+fn test_bytes_order() {
+    let values: Vec<Vec<u8>> = vec![vec![], vec![0], vec![0, 0], vec![0, 1], vec![1], vec![255]];
+    assert_sorted(&values);
+    assert_sorted(&["".to_owned(), "a".to_owned(), "a\0".to_owned(), "ab".to_owned()]);
+    assert_eq!(encode(&vec![0u8, 1]).as_ref(), [0, 255, 1, 0, 1]);
+}
+
+#[test]
+fn test_tuple_order() {
+    let values = [
+        ("huobi".to_owned(), -1i64, Secs(60)),
+        ("huobi".to_owned(), 0, Secs(0)),
+        ("huobi".to_owned(), 0, Secs(60)),
+        ("huobi.".to_owned(), -5, Secs(0)),
+        ("okex".to_owned(), -5, Secs(0)),
+    ];
+    assert_sorted(&values);
+}
+
+#[test]
+fn test_decode_error() {
+    assert!(decode::<u64>(&[0, 0, 4]).is_err());
+    assert!(decode::<u8>(&[0, 4]).is_err());
+    assert!(decode::<String>(b"huobi").is_err());
+    assert!(decode::<String>(&[0, 2]).is_err());
+    assert!(decode::<bool>(&[2]).is_err());
+}
+
+#[test]
+fn test_prefix() {
+    let prefix = encode_prefix(&("huobi".to_owned(), "btc".to_owned()));
+    let key = encode(&("huobi".to_owned(), "btc".to_owned(), Secs(60)));
+    let other = encode(&("huobi".to_owned(), "btcc".to_owned(), Secs(60)));
+    assert!(key.starts_with(&prefix));
+    assert!(!other.starts_with(&prefix));
+    assert!(key < prefix_end(&prefix).unwrap());
+    assert_eq!(prefix_end(&[1, 255, 255]).unwrap().as_ref(), [2]);
+    assert_eq!(prefix_end(&[255, 255]), None);
+}
@@ -3,11 +3,25 @@ use rocksdb::{DBCompactionStyle, Options as InnerOptions, SliceTransform};
 
 pub struct Options {
     pub(in crate) inner: InnerOptions,
+    pub(in crate) retain_wal_for_consumers: bool,
+    pub(in crate) wal_ttl_seconds: u64,
+    pub(in crate) wal_size_limit_mb: u64,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Options {
     pub fn new() -> Self {
-        Options { inner: Self::build_default_options() }
+        Options {
+            inner: Self::build_default_options(),
+            retain_wal_for_consumers: false,
+            wal_ttl_seconds: 0,
+            wal_size_limit_mb: 0,
+        }
     }
 
     pub fn set_table_cache_num_shard_bits(&mut self, num: i32) {
@@ -49,6 +63,39 @@ impl Options {
         self.inner.set_max_background_jobs(num);
     }
 
+    /// Keeps the obsolete WAL files readable by the change feed for `secs` seconds, see
+    /// `set_wal_size_limit_mb`. With both 0, the default, they are deleted right away.
+    pub fn set_wal_ttl_seconds(&mut self, secs: u64) {
+        self.wal_ttl_seconds = secs;
+        self.apply_wal_retention();
+    }
+
+    /// Keeps the obsolete WAL files until they take more than `size` MB, see
+    /// `set_wal_ttl_seconds`.
+    pub fn set_wal_size_limit_mb(&mut self, size: u64) {
+        self.wal_size_limit_mb = size;
+        self.apply_wal_retention();
+    }
+
+    /// Keeps the obsolete WAL files until every registered consumer has committed past them, see
+    /// `Db::register_consumer`. They are deleted when the database is opened or flushed, when
+    /// consumers change and every `WAL_TRIM_INTERVAL` on writes. This overrides
+    /// `set_wal_ttl_seconds`, a size limit set with `set_wal_size_limit_mb` still caps the files.
+    pub fn set_retain_wal_for_consumers(&mut self, retain: bool) {
+        self.retain_wal_for_consumers = retain;
+        self.apply_wal_retention();
+    }
+
+    fn apply_wal_retention(&mut self) {
+        if self.retain_wal_for_consumers {
+            // The obsolete files are archived and never expire, seriesdb trims the archive itself.
+            self.inner.set_wal_ttl_seconds(WAL_TTL_FOREVER);
+        } else {
+            self.inner.set_wal_ttl_seconds(self.wal_ttl_seconds);
+        }
+        self.inner.set_wal_size_limit_mb(self.wal_size_limit_mb);
+    }
+
     fn build_default_options() -> InnerOptions {
         let mut opts = InnerOptions::default();
         opts.create_if_missing(true);
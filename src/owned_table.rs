@@ -0,0 +1,314 @@
+use crate::batch::Batch;
+use crate::db::Db;
+use crate::entry_cursor::EntryCursor;
+use crate::entry_iterator::{Direction, EntryIterator};
+use crate::memcomparable::Memcomparable;
+use crate::table::{build_read_opts, Table};
+use crate::timestamp::Timestamp;
+#[cfg(test)]
+use crate::timestamp::*;
+use crate::types::*;
+#[cfg(test)]
+use crate::utils::*;
+use crate::Error;
+use bytes::Bytes;
+use rocksdb::DBRawIterator;
+use std::fmt;
+use std::ops::RangeBounds;
+use std::sync::Arc;
+
+/// A table handle that owns a reference to its `Db`, so it can be stored in structs and moved
+/// across threads.
+#[derive(Clone)]
+pub struct OwnedTable {
+    db: Arc<Db>,
+    id: TableId,
+    anchor: Bytes,
+}
+
+impl fmt::Debug for OwnedTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "id: {:?}, anchor: {:?}", self.id, self.anchor)
+    }
+}
+
+impl OwnedTable {
+    #[inline]
+    pub(in crate) fn new(db: Arc<Db>, id: TableId, anchor: Bytes) -> Self {
+        OwnedTable { db, id, anchor }
+    }
+
+    /// Borrows this handle as a `Table`, which is free.
+    #[inline]
+    pub fn table(&self) -> Table<'_> {
+        Table::new(&self.db, self.id, self.anchor.clone())
+    }
+
+    #[inline]
+    pub fn put<K, V>(&self, key: K, value: V) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>, {
+        self.table().put(key, value)
+    }
+
+    #[inline]
+    pub fn batch(&self) -> Batch {
+        Batch::new(self.id)
+    }
+
+    #[inline]
+    pub fn write(&self, b: Batch) -> Result<(), Error> {
+        self.table().write(b)
+    }
+
+    #[inline]
+    pub fn delete<K: AsRef<[u8]>>(&self, key: K) -> Result<(), Error> {
+        self.table().delete(key)
+    }
+
+    #[inline]
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Error> {
+        self.table().get(key)
+    }
+
+    #[inline]
+    pub fn put_at<T, V>(&self, ts: T, value: V) -> Result<(), Error>
+    where
+        T: Timestamp,
+        V: AsRef<[u8]>, {
+        self.table().put_at(ts, value)
+    }
+
+    #[inline]
+    pub fn delete_at<T: Timestamp>(&self, ts: T) -> Result<(), Error> {
+        self.table().delete_at(ts)
+    }
+
+    #[inline]
+    pub fn get_at<T: Timestamp>(&self, ts: T) -> Result<Option<Vec<u8>>, Error> {
+        self.table().get_at(ts)
+    }
+
+    #[inline]
+    pub fn get_between<T: Timestamp>(&self, from: T, to: T) -> Result<Vec<(T, Vec<u8>)>, Error> {
+        self.table().get_between(from, to)
+    }
+
+    #[inline]
+    pub fn get_latest<T: Timestamp>(&self) -> Result<Option<(T, Vec<u8>)>, Error> {
+        self.table().get_latest()
+    }
+
+    #[inline]
+    pub fn delete_before<T: Timestamp>(&self, ts: T, compact: bool) -> Result<(), Error> {
+        self.table().delete_before(ts, compact)
+    }
+
+    #[inline]
+    pub fn delete_between<T: Timestamp>(&self, from: T, to: T, compact: bool) -> Result<(), Error> {
+        self.table().delete_between(from, to, compact)
+    }
+
+    #[inline]
+    pub fn keep_last(&self, n: usize, compact: bool) -> Result<(), Error> {
+        self.table().keep_last(n, compact)
+    }
+
+    #[inline]
+    pub fn cursor(&self) -> EntryCursor<'_> {
+        let opts = build_read_opts::<&[u8], _>(self.id, &self.anchor, ..);
+        EntryCursor::new(self.db.inner.raw_iterator_opt(opts), self.id)
+    }
+
+    /// Returns a cursor that keeps the `Db` alive on its own, unlike `cursor`.
+    #[inline]
+    pub fn owned_cursor(&self) -> OwnedCursor {
+        OwnedCursor::new(self.db.clone(), self.id, &self.anchor)
+    }
+
+    #[inline]
+    pub fn iter(&self) -> EntryIterator<'_> {
+        self.range::<&[u8], _>(..)
+    }
+
+    #[inline]
+    pub fn range<K, R>(&self, range: R) -> EntryIterator<'_>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>, {
+        let opts = build_read_opts(self.id, &self.anchor, range);
+        EntryIterator::new(self.db.inner.raw_iterator_opt(opts), Direction::Forward)
+    }
+
+    #[inline]
+    pub fn range_rev<K, R>(&self, range: R) -> EntryIterator<'_>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>, {
+        let opts = build_read_opts(self.id, &self.anchor, range);
+        EntryIterator::new(self.db.inner.raw_iterator_opt(opts), Direction::Reverse)
+    }
+
+    #[inline]
+    pub fn db(&self) -> &Arc<Db> {
+        &self.db
+    }
+
+    #[inline]
+    pub fn id(&self) -> TableId {
+        self.id
+    }
+}
+
+/// An `EntryCursor` that keeps the `Db` alive on its own.
+pub struct OwnedCursor {
+    // Declared before `db` so that it is dropped first.
+    inner: EntryCursor<'static>,
+    db: Arc<Db>,
+}
+
+impl OwnedCursor {
+    fn new(db: Arc<Db>, table_id: TableId, anchor: &Bytes) -> Self {
+        let opts = build_read_opts::<&[u8], _>(table_id, anchor, ..);
+        let inner = db.inner.raw_iterator_opt(opts);
+        // SAFETY: the iterator borrows the `DB` behind the `Arc`, whose address is stable and
+        // which is kept alive by `db` until after `inner` has been dropped. `inner` is never
+        // handed out by value or by mutable reference, so it can't outlive this struct.
+        let inner =
+            unsafe { std::mem::transmute::<DBRawIterator<'_>, DBRawIterator<'static>>(inner) };
+        OwnedCursor { inner: EntryCursor::new(inner, table_id), db }
+    }
+
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        self.inner.is_valid()
+    }
+
+    #[inline]
+    pub fn seek_to_first(&mut self) {
+        self.inner.seek_to_first()
+    }
+
+    #[inline]
+    pub fn seek_to_last(&mut self) {
+        self.inner.seek_to_last()
+    }
+
+    #[inline]
+    pub fn seek<K: AsRef<[u8]>>(&mut self, key: K) {
+        self.inner.seek(key)
+    }
+
+    #[inline]
+    pub fn seek_for_prev<K: AsRef<[u8]>>(&mut self, key: K) {
+        self.inner.seek_for_prev(key)
+    }
+
+    #[inline]
+    pub fn seek_time<T: Timestamp>(&mut self, ts: T) {
+        self.inner.seek_time(ts)
+    }
+
+    #[inline]
+    pub fn seek_time_for_prev<T: Timestamp>(&mut self, ts: T) {
+        self.inner.seek_time_for_prev(ts)
+    }
+
+    #[inline]
+    pub fn seek_prefix<P: Memcomparable>(&mut self, prefix: &P) {
+        self.inner.seek_prefix(prefix)
+    }
+
+    #[inline]
+    pub fn next(&mut self) {
+        self.inner.next()
+    }
+
+    #[inline]
+    pub fn prev(&mut self) {
+        self.inner.prev()
+    }
+
+    #[inline]
+    pub fn key(&self) -> Option<&[u8]> {
+        self.inner.key()
+    }
+
+    #[inline]
+    pub fn time<T: Timestamp>(&self) -> Option<T> {
+        self.inner.time()
+    }
+
+    #[inline]
+    pub fn value(&self) -> Option<&[u8]> {
+        self.inner.value()
+    }
+
+    #[inline]
+    pub fn status(&self) -> Result<(), Error> {
+        self.inner.status()
+    }
+
+    #[inline]
+    pub fn db(&self) -> &Arc<Db> {
+        &self.db
+    }
+}
+
+#[test]
+fn test_owned_table() {
+    run_test("test_owned_table", |db| {
+        let db = Arc::new(db);
+        let table = db.new_owned_table("huobi.btc.usdt.1m").unwrap();
+        table.put(b"k1", b"v1").unwrap();
+        table.put_at(Secs(60), b"v2").unwrap();
+        assert_eq!(table.get(b"k1").unwrap().unwrap(), b"v1");
+        assert_eq!(table.get_latest().unwrap(), Some((Secs(60), b"v2".to_vec())));
+        let borrowed = db.new_table("huobi.btc.usdt.1m").unwrap();
+        assert_eq!(borrowed.id(), table.id());
+        assert_eq!(borrowed.get(b"k1").unwrap().unwrap(), b"v1");
+    })
+}
+
+#[test]
+fn test_owned_table_across_threads() {
+    run_test("test_owned_table_across_threads", |db| {
+        let db = Arc::new(db);
+        let handles: Vec<_> = (0..4u8)
+            .map(|i| {
+                let table = db.new_owned_table("huobi.btc.usdt.1m").unwrap();
+                std::thread::spawn(move || table.put([i], [i]).unwrap())
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let table = db.new_owned_table("huobi.btc.usdt.1m").unwrap();
+        assert_eq!(table.iter().count(), 4);
+    })
+}
+
+#[test]
+fn test_owned_cursor() {
+    run_test("test_owned_cursor", |db| {
+        let db = Arc::new(db);
+        let table = db.new_owned_table("huobi.btc.usdt.1m").unwrap();
+        table.put(b"k1", b"v1").unwrap();
+        table.put(b"k2", b"v2").unwrap();
+        let mut cursor = table.owned_cursor();
+        drop(table);
+        let keys = std::thread::spawn(move || {
+            let mut keys = vec![];
+            cursor.seek_to_first();
+            while cursor.is_valid() {
+                keys.push(cursor.key().unwrap().to_vec());
+                cursor.next();
+            }
+            keys
+        })
+        .join()
+        .unwrap();
+        assert_eq!(keys, vec![b"k1".to_vec(), b"k2".to_vec()]);
+    })
+}
@@ -0,0 +1,120 @@
+use crate::utils::*;
+use bytes::Bytes;
+use rocksdb::WriteBatch;
+
+/// A write batch of inner keys that keeps its range deletions readable by the change feed.
+///
+/// Iterating a raw write batch stops at its first range deletion, so the writes that follow one
+/// would be missing from the feed. A range deletion is recorded as a hint where it was asked for,
+/// while the deletion itself is moved to the end of the batch, split around the keys written
+/// after it so that those writes still take effect.
+#[derive(Default)]
+pub(in crate) struct RawBatch {
+    inner: WriteBatch,
+    ranges: Vec<(Bytes, Bytes)>,
+}
+
+impl RawBatch {
+    #[inline]
+    pub(in crate) fn put<K, V>(&mut self, key: K, value: V)
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>, {
+        self.exclude(key.as_ref());
+        self.inner.put(key, value)
+    }
+
+    #[inline]
+    pub(in crate) fn delete<K: AsRef<[u8]>>(&mut self, key: K) {
+        self.exclude(key.as_ref());
+        self.inner.delete(key)
+    }
+
+    #[inline]
+    pub(in crate) fn delete_range<F, T>(&mut self, from_key: F, to_key: T)
+    where
+        F: AsRef<[u8]>,
+        T: AsRef<[u8]>, {
+        let (from_key, to_key) = (from_key.as_ref(), to_key.as_ref());
+        self.inner.delete(build_delete_range_hint_table_inner_key(from_key, to_key));
+        if from_key < to_key {
+            self.ranges.push((Bytes::copy_from_slice(from_key), Bytes::copy_from_slice(to_key)));
+        }
+    }
+
+    #[inline]
+    pub(in crate) fn into_inner(mut self) -> WriteBatch {
+        for (from_key, to_key) in &self.ranges {
+            self.inner.delete_range(from_key, to_key);
+        }
+        self.inner
+    }
+
+    /// Takes `key` out of the pending ranges, as it is written after them.
+    fn exclude(&mut self, key: &[u8]) {
+        if !self.ranges.iter().any(|(from_key, to_key)| from_key <= key && key < to_key) {
+            return;
+        }
+        let successor = build_successor(key);
+        let mut ranges = Vec::with_capacity(self.ranges.len() + 1);
+        for (from_key, to_key) in self.ranges.drain(..) {
+            if key < from_key || key >= to_key {
+                ranges.push((from_key, to_key));
+                continue;
+            }
+            if from_key[..] < *key {
+                ranges.push((from_key, Bytes::copy_from_slice(key)));
+            }
+            if successor < to_key {
+                ranges.push((successor.clone(), to_key));
+            }
+        }
+        self.ranges = ranges;
+    }
+}
+
+/// Returns the smallest key that sorts after `key`.
+#[inline]
+fn build_successor(key: &[u8]) -> Bytes {
+    let mut successor = Vec::with_capacity(key.len() + 1);
+    successor.extend_from_slice(key);
+    successor.push(0);
+    Bytes::from(successor)
+}
+
+#[test]
+fn test_exclude() {
+    let mut batch = RawBatch::default();
+    batch.delete_range(b"k1", b"k5");
+    batch.put(b"k3", b"v3");
+    batch.delete(b"k1");
+    batch.put(b"k6", b"v6");
+    assert_eq!(
+        batch.ranges,
+        vec![(Bytes::from("k1\0"), Bytes::from("k3")), (Bytes::from("k3\0"), Bytes::from("k5"))]
+    );
+    batch.delete_range(b"k3", b"k4");
+    batch.put(b"k3", b"v3");
+    assert_eq!(batch.ranges.len(), 3);
+    assert_eq!(batch.ranges[2], (Bytes::from("k3\0"), Bytes::from("k4")));
+}
+
+#[test]
+fn test_write_after_delete_range() {
+    run_test("test_write_after_delete_range", |db| {
+        let table = db.new_table("huobi.btc.usdt.1m").unwrap();
+        for key in &["k1", "k2", "k3"] {
+            table.put(key, b"v").unwrap();
+        }
+        let sn = db.get_latest_sn();
+        let mut batch = table.batch();
+        batch.delete_range(b"k1", b"k9");
+        batch.put(b"k2", b"v2");
+        batch.put(b"k4", b"v4");
+        table.write(batch).unwrap();
+        let keys: Vec<Bytes> = table.iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec!["k2", "k4"]);
+        let batch = db.get_updates_since(sn).unwrap().next().unwrap().unwrap();
+        assert_eq!(batch.updates.len(), 3);
+    })
+}
@@ -0,0 +1,528 @@
+#[cfg(test)]
+use crate::db::assert_follows;
+use crate::db::Db;
+use crate::update_batch::UpdateBatch;
+#[cfg(test)]
+use crate::utils::*;
+use crate::wal::earliest_wal_sn;
+use crate::wire::{read_frame, write_frame};
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// A follower opens a connection by sending the sequence number of the last batch it applied, then
+// acks every batch it applies with its sequence number, while the leader streams the batches after
+// that one in frames, keeping the connection alive while idle and ending it with an error frame.
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Message {
+    Batch(UpdateBatch),
+    Keepalive,
+    WalGap { expected: u64, found: Option<u64> },
+    Error(String),
+}
+
+impl Message {
+    fn error(e: &Error) -> Self {
+        match e {
+            Error::WalGap { expected, found } => {
+                Message::WalGap { expected: *expected, found: *found }
+            }
+            e => Message::Error(e.to_string()),
+        }
+    }
+}
+
+pub struct ReplicationOptions {
+    retry_interval: Duration,
+    poll_interval: Duration,
+    read_timeout: Duration,
+}
+
+impl Default for ReplicationOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReplicationOptions {
+    pub fn new() -> Self {
+        ReplicationOptions {
+            retry_interval: Duration::from_secs(1),
+            poll_interval: Duration::from_millis(100),
+            read_timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Sets how long a follower waits before reconnecting to its leader.
+    pub fn set_retry_interval(&mut self, interval: Duration) {
+        self.retry_interval = interval;
+    }
+
+    /// Sets how often a leader waiting for writes checks whether it is stopped and sends its
+    /// followers a keepalive.
+    pub fn set_poll_interval(&mut self, interval: Duration) {
+        self.poll_interval = interval;
+    }
+
+    /// Sets how long a follower waits for its leader to send anything before reconnecting, which
+    /// should be well above the poll interval of the leader.
+    pub fn set_read_timeout(&mut self, timeout: Duration) {
+        self.read_timeout = timeout;
+    }
+}
+
+/// A follower connected to a leader.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FollowerStatus {
+    pub addr: SocketAddr,
+    /// The sequence number of the last batch the follower applied.
+    pub acked_sn: u64,
+}
+
+struct Connection {
+    stream: TcpStream,
+    acked_sn: Arc<AtomicU64>,
+    handle: Option<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct LeaderState {
+    stopped: AtomicBool,
+    next_id: AtomicU64,
+    connections: Mutex<HashMap<u64, Connection>>,
+}
+
+impl LeaderState {
+    #[inline]
+    fn lock_connections(&self) -> MutexGuard<'_, HashMap<u64, Connection>> {
+        self.connections.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Serves the change feed of a database to followers.
+pub struct Leader {
+    addr: SocketAddr,
+    state: Arc<LeaderState>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Leader {
+    pub fn start<A: ToSocketAddrs>(
+        db: Arc<Db>, addr: A, opts: &ReplicationOptions,
+    ) -> Result<Leader, Error> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(LeaderState::default());
+        let poll_interval = opts.poll_interval;
+        let handle = {
+            let state = state.clone();
+            thread::spawn(move || accept(db, listener, state, poll_interval))
+        };
+        Ok(Leader { addr, state, handle: Some(handle) })
+    }
+
+    #[inline]
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn followers(&self) -> Vec<FollowerStatus> {
+        let connections = self.state.lock_connections();
+        let mut followers: Vec<FollowerStatus> = connections
+            .values()
+            .filter_map(|connection| {
+                Some(FollowerStatus {
+                    addr: connection.stream.peer_addr().ok()?,
+                    acked_sn: connection.acked_sn.load(Ordering::SeqCst),
+                })
+            })
+            .collect();
+        followers.sort_by_key(|follower| follower.addr);
+        followers
+    }
+
+    /// Closes every connection and waits for the threads serving them to end.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        let handle = match self.handle.take() {
+            Some(handle) => handle,
+            None => return,
+        };
+        self.state.stopped.store(true, Ordering::SeqCst);
+        // Wakes the accepting thread up.
+        let _ = TcpStream::connect(self.addr);
+        let _ = handle.join();
+        let handles: Vec<JoinHandle<()>> = self
+            .state
+            .lock_connections()
+            .values_mut()
+            .filter_map(|connection| {
+                let _ = connection.stream.shutdown(Shutdown::Both);
+                connection.handle.take()
+            })
+            .collect();
+        for handle in handles {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for Leader {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn accept(db: Arc<Db>, listener: TcpListener, state: Arc<LeaderState>, poll_interval: Duration) {
+    for stream in listener.incoming() {
+        if state.stopped.load(Ordering::SeqCst) {
+            break;
+        }
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        let id = state.next_id.fetch_add(1, Ordering::SeqCst);
+        let acked_sn = Arc::new(AtomicU64::new(0));
+        let (db, state_clone, acked_sn_clone) = (db.clone(), state.clone(), acked_sn.clone());
+        let stream_clone = match stream.try_clone() {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        let mut connections = state.lock_connections();
+        // Hold the lock until the handle is stored, so that the connection can't remove itself
+        // before it is added.
+        let handle = thread::spawn(move || {
+            let _ = serve(&db, stream_clone, acked_sn_clone, &state_clone.stopped, poll_interval);
+            // Released before leaving the connections, so that a stopped leader holds no handle.
+            drop(db);
+            state_clone.lock_connections().remove(&id);
+        });
+        connections.insert(id, Connection { stream, acked_sn, handle: Some(handle) });
+    }
+}
+
+fn serve(
+    db: &Db, stream: TcpStream, acked_sn: Arc<AtomicU64>, stopped: &AtomicBool,
+    poll_interval: Duration,
+) -> Result<(), Error> {
+    stream.set_nodelay(true)?;
+    let mut reader = stream.try_clone()?;
+    let mut sn = [0; 8];
+    reader.read_exact(&mut sn)?;
+    let sn = u64::from_be_bytes(sn);
+    acked_sn.store(sn, Ordering::SeqCst);
+    // Acks are read aside, noticing the follower going away while there is nothing to send.
+    let closed = Arc::new(AtomicBool::new(false));
+    let acks = {
+        let closed = closed.clone();
+        thread::spawn(move || {
+            let mut sn = [0; 8];
+            while reader.read_exact(&mut sn).is_ok() {
+                acked_sn.store(u64::from_be_bytes(sn), Ordering::SeqCst);
+            }
+            closed.store(true, Ordering::SeqCst);
+        })
+    };
+    let result = send_batches(db, &stream, sn, &closed, stopped, poll_interval);
+    if let Err(e) = &result {
+        let _ = write_frame(&mut &stream, &Message::error(e));
+    }
+    let _ = stream.shutdown(Shutdown::Both);
+    let _ = acks.join();
+    result
+}
+
+fn send_batches(
+    db: &Db, stream: &TcpStream, sn: u64, closed: &AtomicBool, stopped: &AtomicBool,
+    poll_interval: Duration,
+) -> Result<(), Error> {
+    check_start(db, sn)?;
+    let mut writer = BufWriter::new(stream);
+    let mut subscription = db.subscribe(sn);
+    while !stopped.load(Ordering::SeqCst) && !closed.load(Ordering::SeqCst) {
+        match subscription.next_timeout(Some(poll_interval))? {
+            Some(batch) => write_frame(&mut writer, &Message::Batch(batch))?,
+            None => write_frame(&mut writer, &Message::Keepalive)?,
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// A follower starting from scratch needs every batch, which the WAL holds only until its first
+/// file is purged. The feed skips the first batch, which holds no more than the table id seed.
+fn check_start(db: &Db, sn: u64) -> Result<(), Error> {
+    if sn > 0 || db.get_latest_sn() == 0 {
+        return Ok(());
+    }
+    match earliest_wal_sn(db.inner.path())? {
+        Some(1) => Ok(()),
+        found => Err(Error::WalGap { expected: 1, found }),
+    }
+}
+
+#[derive(Default)]
+struct FollowerState {
+    stopped: Mutex<bool>,
+    wake: Condvar,
+    stream: Mutex<Option<TcpStream>>,
+    last_error: Mutex<Option<String>>,
+}
+
+impl FollowerState {
+    #[inline]
+    fn is_stopped(&self) -> bool {
+        *self.stopped.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Sleeps for `timeout` unless stopped first, returning whether it was stopped.
+    fn wait_stopped(&self, timeout: Duration) -> bool {
+        let stopped = self.stopped.lock().unwrap_or_else(|e| e.into_inner());
+        let (stopped, _) = self
+            .wake
+            .wait_timeout_while(stopped, timeout, |stopped| !*stopped)
+            .unwrap_or_else(|e| e.into_inner());
+        *stopped
+    }
+
+    #[inline]
+    fn lock_stream(&self) -> MutexGuard<'_, Option<TcpStream>> {
+        self.stream.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    #[inline]
+    fn set_last_error(&self, e: Error) {
+        *self.last_error.lock().unwrap_or_else(|e| e.into_inner()) = Some(e.to_string());
+    }
+}
+
+/// Applies the change feed of a leader to a database, reconnecting whenever the connection is
+/// lost and resuming from the last batch applied. Gives up with `WalGap` as the last error if the
+/// leader no longer holds the batches after that one, which takes a fresh copy of the leader to
+/// recover from.
+pub struct Follower {
+    state: Arc<FollowerState>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Follower {
+    pub fn start(db: Arc<Db>, leader_addr: SocketAddr, opts: &ReplicationOptions) -> Follower {
+        let state = Arc::new(FollowerState::default());
+        let (retry_interval, read_timeout) = (opts.retry_interval, opts.read_timeout);
+        let handle = {
+            let state = state.clone();
+            thread::spawn(move || loop {
+                if state.is_stopped() {
+                    break;
+                }
+                let result = follow(&db, leader_addr, &state, read_timeout);
+                state.lock_stream().take();
+                if let Err(e) = result {
+                    let gap = matches!(e, Error::WalGap { .. });
+                    state.set_last_error(e);
+                    // Reconnecting won't bring back what the leader no longer holds.
+                    if gap {
+                        break;
+                    }
+                }
+                if state.wait_stopped(retry_interval) {
+                    break;
+                }
+            })
+        };
+        Follower { state, handle: Some(handle) }
+    }
+
+    #[inline]
+    pub fn is_connected(&self) -> bool {
+        self.state.lock_stream().is_some()
+    }
+
+    /// Returns the error that ended the last connection, if any.
+    #[inline]
+    pub fn last_error(&self) -> Option<String> {
+        self.state.last_error.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Closes the connection and waits for the follower to end.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        let handle = match self.handle.take() {
+            Some(handle) => handle,
+            None => return,
+        };
+        *self.state.stopped.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        self.state.wake.notify_all();
+        if let Some(stream) = self.state.lock_stream().as_ref() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        let _ = handle.join();
+    }
+}
+
+impl Drop for Follower {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn follow(
+    db: &Db, leader_addr: SocketAddr, state: &FollowerState, read_timeout: Duration,
+) -> Result<(), Error> {
+    let stream = TcpStream::connect(leader_addr)?;
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(read_timeout))?;
+    {
+        let mut current = state.lock_stream();
+        // Checked under the lock, so that `stop` either sees the stream or stops us here.
+        if state.is_stopped() {
+            return Ok(());
+        }
+        *current = Some(stream.try_clone()?);
+    }
+    let mut writer = stream.try_clone()?;
+    writer.write_all(&db.get_applied_sn()?.unwrap_or(0).to_be_bytes())?;
+    let mut reader = BufReader::new(stream);
+    while let Some(message) = read_frame(&mut reader)? {
+        match message {
+            Message::Batch(batch) => {
+                db.apply(&batch)?;
+                writer.write_all(&batch.sn.to_be_bytes())?;
+            }
+            Message::Keepalive => {}
+            Message::WalGap { expected, found } => return Err(Error::WalGap { expected, found }),
+            Message::Error(message) => return Err(Error::Replication(message)),
+        }
+    }
+    if state.is_stopped() {
+        return Ok(());
+    }
+    Err(Error::Io(ErrorKind::ConnectionAborted.into()))
+}
+
+#[cfg(test)]
+fn wait_until<F: FnMut() -> bool>(mut condition: F) {
+    for _ in 0..500 {
+        if condition() {
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("Timed out");
+}
+
+#[test]
+fn test_replication() {
+    run_test("test_replication_leader", |leader_db| {
+        run_test("test_replication_follower", |follower_db| {
+            let (leader_db, follower_db) = (Arc::new(leader_db), Arc::new(follower_db));
+            let mut opts = ReplicationOptions::new();
+            opts.set_retry_interval(Duration::from_millis(20));
+            opts.set_poll_interval(Duration::from_millis(20));
+            opts.set_read_timeout(Duration::from_millis(200));
+            let leader = Leader::start(leader_db.clone(), "127.0.0.1:0", &opts).unwrap();
+            let addr = leader.local_addr();
+            let follower = Follower::start(follower_db.clone(), addr, &opts);
+            let table = leader_db.new_table("huobi.btc.usdt.1m").unwrap();
+            for i in 0..100u32 {
+                table.put(i.to_be_bytes(), b"v").unwrap();
+            }
+            let mut batch = table.batch();
+            batch.delete_range(0u32.to_be_bytes(), 50u32.to_be_bytes());
+            batch.put(10u32.to_be_bytes(), b"v10");
+            table.write(batch).unwrap();
+            table.put(b"marker", b"1").unwrap();
+            let caught_up =
+                || follower_db.get_applied_sn().unwrap() == Some(leader_db.get_latest_sn());
+            wait_until(caught_up);
+            assert_follows(&leader_db, &follower_db);
+            wait_until(|| {
+                leader.followers().iter().any(|f| f.acked_sn == leader_db.get_latest_sn())
+            });
+            // Keepalives hold the idle connection open past the read timeout.
+            thread::sleep(Duration::from_millis(400));
+            assert!(follower.is_connected());
+            assert!(follower.last_error().is_none());
+
+            // The follower reconnects to a restarted leader and resumes where it stopped.
+            leader.stop();
+            let table = leader_db.new_table("huobi.btc.usdt.5m").unwrap();
+            table.put(b"k1", b"v1").unwrap();
+            leader_db.destroy_table("huobi.btc.usdt.1m").unwrap();
+            table.put(b"marker", b"2").unwrap();
+            let leader = Leader::start(leader_db.clone(), addr, &opts).unwrap();
+            wait_until(caught_up);
+            assert_follows(&leader_db, &follower_db);
+            assert!(follower.last_error().is_some());
+
+            // And to the same leader after restarting itself.
+            follower.stop();
+            leader_db.new_table("huobi.btc.usdt.15m").unwrap().put(b"k1", b"v1").unwrap();
+            let follower = Follower::start(follower_db.clone(), addr, &opts);
+            wait_until(caught_up);
+            assert_follows(&leader_db, &follower_db);
+            follower.stop();
+            leader.stop();
+        })
+    })
+}
+
+#[test]
+fn test_follow_purged_leader() {
+    run_test("test_follow_purged_leader_leader", |leader_db| {
+        run_test("test_follow_purged_leader_follower", |follower_db| {
+            let table = leader_db.new_table("huobi.btc.usdt.1m").unwrap();
+            table.put(b"k1", b"v1").unwrap();
+            leader_db.flush().unwrap();
+            table.put(b"k2", b"v2").unwrap();
+            drop(table);
+            // The obsolete WAL is deleted in the background after the flush.
+            let path = leader_db.inner.path().to_owned();
+            wait_until(|| earliest_wal_sn(&path).unwrap() != Some(1));
+            let (leader_db, follower_db) = (Arc::new(leader_db), Arc::new(follower_db));
+            let mut opts = ReplicationOptions::new();
+            opts.set_retry_interval(Duration::from_millis(20));
+            let leader = Leader::start(leader_db, "127.0.0.1:0", &opts).unwrap();
+            let follower = Follower::start(follower_db.clone(), leader.local_addr(), &opts);
+            wait_until(|| follower.last_error().is_some());
+            assert!(follower.last_error().unwrap().starts_with("WAL gap"));
+            // The follower gives up rather than applying the batches left.
+            thread::sleep(Duration::from_millis(100));
+            assert!(!follower.is_connected());
+            assert_eq!(follower_db.get_applied_sn().unwrap(), None);
+            follower.stop();
+            leader.stop();
+        })
+    })
+}
+
+#[test]
+fn test_follow_silent_leader() {
+    run_test("test_follow_silent_leader", |db| {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut opts = ReplicationOptions::new();
+        opts.set_retry_interval(Duration::from_millis(20));
+        opts.set_read_timeout(Duration::from_millis(50));
+        let follower = Follower::start(Arc::new(db), listener.local_addr().unwrap(), &opts);
+        // Accepts and holds the connections without sending anything.
+        let (first, _) = listener.accept().unwrap();
+        let (second, _) = listener.accept().unwrap();
+        assert!(follower.last_error().is_some());
+        drop((first, second));
+        follower.stop();
+    })
+}
@@ -0,0 +1,209 @@
+use crate::db::Db;
+use crate::entry_cursor::EntryCursor;
+use crate::entry_iterator::{Direction, EntryIterator};
+use crate::table::build_read_opts;
+#[cfg(test)]
+use crate::timestamp::Secs;
+use crate::timestamp::Timestamp;
+use crate::types::*;
+#[cfg(test)]
+use crate::update::Update;
+use crate::utils::*;
+use crate::Error;
+use bytes::Bytes;
+use rocksdb::Snapshot as InnerSnapshot;
+use std::ops::RangeBounds;
+
+/// A consistent, read-only view of every table as of `get_latest_sn`.
+pub struct Snapshot<'a> {
+    db: &'a Db,
+    inner: InnerSnapshot<'a>,
+    sn: u64,
+}
+
+impl<'a> Snapshot<'a> {
+    pub(in crate) fn new(db: &'a Db) -> Self {
+        // rocksdb doesn't expose the sn of a snapshot, so the writes are held off while reading
+        // the latest sn and taking the snapshot.
+        let _guard = db.block_writes();
+        let sn = db.get_latest_sn();
+        let inner = db.inner.snapshot();
+        Snapshot { db, inner, sn }
+    }
+
+    /// Returns the sn of the last write visible in this snapshot, pass it to
+    /// `Db::get_updates_since` to follow the writes made after it.
+    #[inline]
+    pub fn get_latest_sn(&self) -> u64 {
+        self.sn
+    }
+
+    #[inline]
+    pub fn table(&self, name: &str) -> Result<Option<SnapshotTable<'_>>, Error> {
+        if let Some(id) = self.get_table_id_by_name(name)? {
+            Ok(Some(self.build_table(id)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    #[inline]
+    pub(in crate) fn build_table(&self, id: TableId) -> SnapshotTable<'_> {
+        SnapshotTable { snapshot: &self.inner, id, anchor: build_table_anchor(id) }
+    }
+
+    #[inline]
+    pub fn get_table_id_by_name(&self, name: &str) -> Result<Option<TableId>, Error> {
+        let name_to_id_table_inner_key = build_name_to_id_table_inner_key(name);
+        if let Some(id) = self.inner.get(name_to_id_table_inner_key)? {
+            Ok(Some(u8s_to_table_id(id.as_ref())))
+        } else {
+            Ok(None)
+        }
+    }
+
+    #[inline]
+    pub fn db(&self) -> &Db {
+        self.db
+    }
+}
+
+/// A table as seen by a `Snapshot`.
+pub struct SnapshotTable<'a> {
+    snapshot: &'a InnerSnapshot<'a>,
+    id: TableId,
+    anchor: Bytes,
+}
+
+impl<'a> SnapshotTable<'a> {
+    #[inline]
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.snapshot.get(build_inner_key(self.id, key))?)
+    }
+
+    #[inline]
+    pub fn get_at<T: Timestamp>(&self, ts: T) -> Result<Option<Vec<u8>>, Error> {
+        self.get(ts.to_key())
+    }
+
+    #[inline]
+    pub fn cursor(&self) -> EntryCursor<'_> {
+        let opts = build_read_opts::<&[u8], _>(self.id, &self.anchor, ..);
+        EntryCursor::new(self.snapshot.raw_iterator_opt(opts), self.id)
+    }
+
+    #[inline]
+    pub fn iter(&self) -> EntryIterator<'_> {
+        self.range::<&[u8], _>(..)
+    }
+
+    #[inline]
+    pub fn range<K, R>(&self, range: R) -> EntryIterator<'_>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>, {
+        let opts = build_read_opts(self.id, &self.anchor, range);
+        EntryIterator::new(self.snapshot.raw_iterator_opt(opts), Direction::Forward)
+    }
+
+    #[inline]
+    pub fn range_rev<K, R>(&self, range: R) -> EntryIterator<'_>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>, {
+        let opts = build_read_opts(self.id, &self.anchor, range);
+        EntryIterator::new(self.snapshot.raw_iterator_opt(opts), Direction::Reverse)
+    }
+
+    #[inline]
+    pub fn id(&self) -> TableId {
+        self.id
+    }
+}
+
+#[test]
+fn test_snapshot_get() {
+    run_test("test_snapshot_get", |db| {
+        let table = db.new_table("huobi.btc.usdt.1m").unwrap();
+        table.put(b"k1", b"v1").unwrap();
+        table.put_at(Secs(60), b"v2").unwrap();
+        let snapshot = db.snapshot();
+        table.put(b"k1", b"v3").unwrap();
+        table.delete_at(Secs(60)).unwrap();
+        let snapshot_table = snapshot.table("huobi.btc.usdt.1m").unwrap().unwrap();
+        assert_eq!(snapshot_table.get(b"k1").unwrap().unwrap(), b"v1");
+        assert_eq!(snapshot_table.get_at(Secs(60)).unwrap().unwrap(), b"v2");
+        assert_eq!(table.get(b"k1").unwrap().unwrap(), b"v3");
+    })
+}
+
+#[test]
+fn test_snapshot_scan() {
+    run_test("test_snapshot_scan", |db| {
+        let table = db.new_table("huobi.btc.usdt.1m").unwrap();
+        table.put(b"k1", b"v1").unwrap();
+        table.put(b"k2", b"v2").unwrap();
+        let snapshot = db.snapshot();
+        table.put(b"k3", b"v3").unwrap();
+        table.delete(b"k1").unwrap();
+        let snapshot_table = snapshot.table("huobi.btc.usdt.1m").unwrap().unwrap();
+        let keys: Vec<Bytes> = snapshot_table.iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec!["k1", "k2"]);
+        let keys: Vec<Bytes> = snapshot_table.range_rev("k1"..).map(|(k, _)| k).collect();
+        assert_eq!(keys, vec!["k2", "k1"]);
+        let mut cursor = snapshot_table.cursor();
+        cursor.seek_to_last();
+        assert_eq!(cursor.key().unwrap(), b"k2");
+    })
+}
+
+#[test]
+fn test_snapshot_across_tables() {
+    run_test("test_snapshot_across_tables", |db| {
+        let table_1m = db.new_table("huobi.btc.usdt.1m").unwrap();
+        table_1m.put(b"k1", b"v1").unwrap();
+        let snapshot = db.snapshot();
+        let table_5m = db.new_table("huobi.btc.usdt.5m").unwrap();
+        table_5m.put(b"k1", b"v1").unwrap();
+        assert!(snapshot.table("huobi.btc.usdt.1m").unwrap().is_some());
+        assert!(snapshot.table("huobi.btc.usdt.5m").unwrap().is_none());
+    })
+}
+
+#[test]
+fn test_snapshot_sn() {
+    run_test("test_snapshot_sn", |db| {
+        let table = db.new_table("huobi.btc.usdt.1m").unwrap();
+        table.put(b"k1", b"v1").unwrap();
+        let snapshot = db.snapshot();
+        assert_eq!(snapshot.get_latest_sn(), db.get_latest_sn());
+        table.put(b"k2", b"v2").unwrap();
+        let mut iter = db.get_updates_since(snapshot.get_latest_sn()).unwrap();
+        let batch = iter.next().unwrap().unwrap();
+        assert_eq!(batch.sn, snapshot.get_latest_sn() + 1);
+        assert!(
+            matches!(&batch.updates[..], [Update::Put { key, .. }] if extract_key(key) == b"k2")
+        );
+    })
+}
+
+#[test]
+fn test_snapshot_sn_under_writes() {
+    run_test("test_snapshot_sn_under_writes", |db| {
+        let table = db.new_table("huobi.btc.usdt.1m").unwrap();
+        let base_sn = db.get_latest_sn();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for i in 0..2000u32 {
+                    table.put(i.to_be_bytes(), b"v").unwrap();
+                }
+            });
+            for _ in 0..20 {
+                let snapshot = db.snapshot();
+                let snapshot_table = snapshot.table("huobi.btc.usdt.1m").unwrap().unwrap();
+                let count = snapshot_table.iter().count() as u64;
+                assert_eq!(snapshot.get_latest_sn(), base_sn + count);
+            }
+        });
+    })
+}
@@ -0,0 +1,157 @@
+use crate::db::Db;
+use crate::update_batch::UpdateBatch;
+use crate::update_iterator::UpdateIterator;
+#[cfg(test)]
+use crate::utils::*;
+use crate::Error;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+#[cfg(test)]
+use std::sync::Arc;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Wakes the subscriptions waiting for writes.
+#[derive(Default)]
+pub(in crate) struct WriteSignal {
+    generation: AtomicU64,
+    waiters: AtomicUsize,
+    lock: Mutex<()>,
+    cond: Condvar,
+}
+
+impl WriteSignal {
+    #[inline]
+    pub(in crate) fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Called after every write, it only takes the lock when someone is waiting.
+    #[inline]
+    pub(in crate) fn notify(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+            self.cond.notify_all();
+        }
+    }
+
+    /// Waits until a write newer than `generation` or the deadline, returning false on timeout.
+    pub(in crate) fn wait(&self, generation: u64, deadline: Option<Instant>) -> bool {
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        let mut guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let notified = loop {
+            if self.generation() != generation {
+                break true;
+            }
+            match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break false;
+                    }
+                    guard = match self.cond.wait_timeout(guard, deadline - now) {
+                        Ok((guard, _)) => guard,
+                        Err(e) => e.into_inner().0,
+                    };
+                }
+                None => guard = self.cond.wait(guard).unwrap_or_else(|e| e.into_inner()),
+            }
+        };
+        drop(guard);
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+        notified
+    }
+}
+
+/// Tails the change feed, waiting for the batches written after the last one it returned.
+pub struct Subscription<'a> {
+    db: &'a Db,
+    inner: Option<UpdateIterator>,
+    sn: u64,
+    next_sn: Option<u64>,
+}
+
+impl<'a> Subscription<'a> {
+    #[inline]
+    pub(in crate) fn new(db: &'a Db, sn: u64) -> Self {
+        Subscription { db, inner: None, sn, next_sn: None }
+    }
+
+    /// Returns the next batch, blocking until one is written. Returns `None` if `timeout` passes
+    /// first, or never if it is `None`.
+    pub fn next_timeout(
+        &mut self, timeout: Option<Duration>,
+    ) -> Result<Option<UpdateBatch>, Error> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            // Read before looking for updates, so that a write in between isn't missed.
+            let generation = self.db.write_signal.generation();
+            if self.inner.is_none() {
+                self.inner = Some(self.db.resume_updates(self.sn, self.next_sn)?);
+            }
+            if let Some(batch) = self.inner.as_mut().unwrap().next() {
+                let batch = batch?;
+                self.sn = batch.sn;
+                return Ok(Some(batch));
+            }
+            // The feed is read again from the last batch once something is written.
+            self.next_sn = self.inner.take().and_then(|inner| inner.next_sn());
+            if !self.db.write_signal.wait(generation, deadline) {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Returns the sequence number of the last batch returned, which the subscription can be
+    /// resumed from.
+    #[inline]
+    pub fn sn(&self) -> u64 {
+        self.sn
+    }
+}
+
+impl<'a> Iterator for Subscription<'a> {
+    type Item = Result<UpdateBatch, Error>;
+
+    /// Blocks until the next batch is written.
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_timeout(None).transpose()
+    }
+}
+
+#[test]
+fn test_subscribe() {
+    run_test("test_subscribe", |db| {
+        let table = db.new_table("huobi.btc.usdt.1m").unwrap();
+        let mut subscription = db.subscribe(db.get_latest_sn());
+        table.put(b"k1", b"v1").unwrap();
+        let batch = subscription.next_timeout(Some(Duration::from_secs(5))).unwrap().unwrap();
+        assert_eq!(batch.sn, db.get_latest_sn());
+        assert_eq!(subscription.sn(), batch.sn);
+        let started_at = Instant::now();
+        assert!(subscription.next_timeout(Some(Duration::from_millis(50))).unwrap().is_none());
+        assert!(started_at.elapsed() >= Duration::from_millis(50));
+        let sn = subscription.sn();
+        drop(subscription);
+        drop(table);
+        let db = Arc::new(db);
+        let writer = {
+            let db = db.clone();
+            std::thread::spawn(move || {
+                let table = db.new_table("huobi.btc.usdt.1m").unwrap();
+                for i in 0..10u32 {
+                    std::thread::sleep(Duration::from_millis(5));
+                    table.put(i.to_be_bytes(), b"v").unwrap();
+                }
+            })
+        };
+        let mut subscription = db.subscribe(sn);
+        for _ in 0..10 {
+            let batch = subscription.next_timeout(Some(Duration::from_secs(5))).unwrap().unwrap();
+            assert_eq!(batch.updates.len(), 1);
+        }
+        writer.join().unwrap();
+        assert_eq!(subscription.sn(), db.get_latest_sn());
+    })
+}
@@ -1,12 +1,18 @@
 use crate::batch::Batch;
-use crate::db::Db;
+use crate::db::{clear_table, Db};
 use crate::entry_cursor::EntryCursor;
+use crate::entry_iterator::{Direction, EntryIterator};
+use crate::raw_batch::RawBatch;
+use crate::timestamp::Timestamp;
+#[cfg(test)]
+use crate::timestamp::*;
 use crate::types::*;
 use crate::utils::*;
 use crate::Error;
-use bytes::Bytes;
+use bytes::{BufMut, Bytes, BytesMut};
 use rocksdb::ReadOptions;
 use std::fmt;
+use std::ops::{Bound, RangeBounds};
 
 #[derive(Clone)]
 pub struct Table<'a> {
@@ -23,7 +29,7 @@ impl<'a> fmt::Debug for Table<'a> {
 
 impl<'a> Table<'a> {
     #[inline]
-    pub(in crate) fn new(db: &Db, id: TableId, anchor: Bytes) -> Table {
+    pub(in crate) fn new(db: &Db, id: TableId, anchor: Bytes) -> Table<'_> {
         Table { db, id, anchor }
     }
 
@@ -31,9 +37,8 @@ impl<'a> Table<'a> {
     pub fn put<K, V>(&self, key: K, value: V) -> Result<(), Error>
     where
         K: AsRef<[u8]>,
-        V: AsRef<[u8]>,
-    {
-        self.db.inner.put(build_inner_key(self.id, key), value)
+        V: AsRef<[u8]>, {
+        self.db.put_raw(build_inner_key(self.id, key), value)
     }
 
     #[inline]
@@ -43,24 +48,139 @@ impl<'a> Table<'a> {
 
     #[inline]
     pub fn write(&self, b: Batch) -> Result<(), Error> {
-        self.db.inner.write(b.inner)
+        self.db.write_raw(b.inner)
     }
 
     #[inline]
     pub fn delete<K: AsRef<[u8]>>(&self, key: K) -> Result<(), Error> {
-        self.db.inner.delete(build_inner_key(self.id, key))
+        self.db.delete_raw(build_inner_key(self.id, key))
     }
 
     #[inline]
     pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Vec<u8>>, Error> {
-        self.db.inner.get(build_inner_key(self.id, key))
+        Ok(self.db.inner.get(build_inner_key(self.id, key))?)
     }
 
     #[inline]
-    pub fn cursor(&self) -> EntryCursor {
-        let mut opts = ReadOptions::default();
-        opts.set_prefix_same_as_start(true);
-        EntryCursor::new(self.db.inner.raw_iterator_opt(opts), self.id, &self.anchor)
+    pub fn put_at<T, V>(&self, ts: T, value: V) -> Result<(), Error>
+    where
+        T: Timestamp,
+        V: AsRef<[u8]>, {
+        self.db.put_raw(build_inner_key(self.id, ts.to_key()), value)
+    }
+
+    #[inline]
+    pub fn delete_at<T: Timestamp>(&self, ts: T) -> Result<(), Error> {
+        self.db.delete_raw(build_inner_key(self.id, ts.to_key()))
+    }
+
+    #[inline]
+    pub fn get_at<T: Timestamp>(&self, ts: T) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.db.inner.get(build_inner_key(self.id, ts.to_key()))?)
+    }
+
+    /// Returns entries whose timestamp falls in `[from, to)`, in chronological order.
+    pub fn get_between<T: Timestamp>(&self, from: T, to: T) -> Result<Vec<(T, Vec<u8>)>, Error> {
+        let to_key = to.to_key();
+        let mut result = Vec::new();
+        let mut cursor = self.cursor();
+        cursor.seek_time(from);
+        while cursor.is_valid() {
+            let key = cursor.key().unwrap();
+            if key >= &to_key[..] {
+                break;
+            }
+            if let Some(ts) = T::from_key(key) {
+                result.push((ts, cursor.value().unwrap().to_vec()));
+            }
+            cursor.next();
+        }
+        cursor.status()?;
+        Ok(result)
+    }
+
+    /// Returns the most recent entry, if any.
+    pub fn get_latest<T: Timestamp>(&self) -> Result<Option<(T, Vec<u8>)>, Error> {
+        let mut cursor = self.cursor();
+        cursor.seek_to_last();
+        while cursor.is_valid() {
+            if let Some(ts) = cursor.time() {
+                return Ok(Some((ts, cursor.value().unwrap().to_vec())));
+            }
+            cursor.prev();
+        }
+        cursor.status()?;
+        Ok(None)
+    }
+
+    /// Deletes the entries whose timestamp is before `ts`, then compacts their range if
+    /// `compact` is set so that the disk space is reclaimed right away.
+    #[inline]
+    pub fn delete_before<T: Timestamp>(&self, ts: T, compact: bool) -> Result<(), Error> {
+        self.delete_range_and_compact([], ts.to_key(), compact)
+    }
+
+    /// Deletes the entries whose timestamp falls in `[from, to)`, see `delete_before`.
+    #[inline]
+    pub fn delete_between<T: Timestamp>(&self, from: T, to: T, compact: bool) -> Result<(), Error> {
+        self.delete_range_and_compact(from.to_key(), to.to_key(), compact)
+    }
+
+    /// Deletes every entry but the last `n` ones, see `delete_before`.
+    pub fn keep_last(&self, n: usize, compact: bool) -> Result<(), Error> {
+        if n == 0 {
+            let mut batch = RawBatch::default();
+            clear_table(&mut batch, self.id);
+            self.db.write_raw(batch)?;
+            if compact {
+                self.db.inner.compact_range(Some(self.id), Some(&self.anchor));
+            }
+            return Ok(());
+        }
+        let mut cursor = self.cursor();
+        cursor.seek_to_last();
+        for _ in 1..n {
+            if !cursor.is_valid() {
+                break;
+            }
+            cursor.prev();
+        }
+        match cursor.key() {
+            Some(key) => {
+                let key = key.to_vec();
+                self.delete_range_and_compact([], key, compact)
+            }
+            None => cursor.status(),
+        }
+    }
+
+    #[inline]
+    pub fn cursor(&self) -> EntryCursor<'_> {
+        let opts = build_read_opts::<&[u8], _>(self.id, &self.anchor, ..);
+        EntryCursor::new(self.db.inner.raw_iterator_opt(opts), self.id)
+    }
+
+    #[inline]
+    pub fn iter(&self) -> EntryIterator<'_> {
+        self.range::<&[u8], _>(..)
+    }
+
+    /// Iterates over the entries whose keys fall in `range`, in ascending key order.
+    #[inline]
+    pub fn range<K, R>(&self, range: R) -> EntryIterator<'_>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>, {
+        self.build_iterator(range, Direction::Forward)
+    }
+
+    /// Iterates over the entries whose keys fall in `range`, in descending key order.
+    #[inline]
+    pub fn range_rev<K, R>(&self, range: R) -> EntryIterator<'_>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>, {
+        self.build_iterator(range, Direction::Reverse)
     }
 
     #[inline]
@@ -72,6 +192,63 @@ impl<'a> Table<'a> {
     pub fn id(&self) -> TableId {
         self.id
     }
+
+    #[inline]
+    fn delete_range_and_compact<F, T>(
+        &self, from_key: F, to_key: T, compact: bool,
+    ) -> Result<(), Error>
+    where
+        F: AsRef<[u8]>,
+        T: AsRef<[u8]>, {
+        let mut batch = self.batch();
+        batch.delete_range(&from_key, &to_key);
+        self.write(batch)?;
+        if compact {
+            let from_key = build_inner_key(self.id, from_key);
+            let to_key = build_inner_key(self.id, to_key);
+            self.db.inner.compact_range(Some(from_key), Some(to_key));
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn build_iterator<K, R>(&self, range: R, direction: Direction) -> EntryIterator<'_>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>, {
+        let opts = build_read_opts(self.id, &self.anchor, range);
+        EntryIterator::new(self.db.inner.raw_iterator_opt(opts), direction)
+    }
+}
+
+/// Builds read options bounded to the part of the table that falls in `range`.
+pub(in crate) fn build_read_opts<K, R>(table_id: TableId, anchor: &Bytes, range: R) -> ReadOptions
+where
+    K: AsRef<[u8]>,
+    R: RangeBounds<K>, {
+    let lower_bound = match range.start_bound() {
+        Bound::Included(key) => build_inner_key(table_id, key),
+        Bound::Excluded(key) => build_successor_inner_key(table_id, key),
+        Bound::Unbounded => Bytes::copy_from_slice(&table_id),
+    };
+    let upper_bound = match range.end_bound() {
+        Bound::Included(key) => build_successor_inner_key(table_id, key),
+        Bound::Excluded(key) => build_inner_key(table_id, key),
+        Bound::Unbounded => anchor.clone(),
+    };
+    let mut opts = ReadOptions::default();
+    opts.set_total_order_seek(true);
+    opts.set_iterate_lower_bound(lower_bound.to_vec());
+    opts.set_iterate_upper_bound(upper_bound.to_vec());
+    opts
+}
+
+/// Builds the smallest inner key that sorts after `key`.
+#[inline]
+fn build_successor_inner_key<K: AsRef<[u8]>>(table_id: TableId, key: K) -> Bytes {
+    let mut buf = BytesMut::from(build_inner_key(table_id, key).as_ref());
+    buf.put_u8(0);
+    buf.freeze()
 }
 
 #[test]
@@ -110,3 +287,94 @@ fn test_delete() {
         assert!(result.unwrap().is_none());
     })
 }
+
+#[test]
+fn test_put_at_and_get_at() {
+    run_test("test_put_at_and_get_at", |db| {
+        let name = "huobi.btc.usdt.1min";
+        let table = db.new_table(name).unwrap();
+        table.put_at(Secs(1_600_000_000), b"v1").unwrap();
+        assert_eq!(table.get_at(Secs(1_600_000_000)).unwrap().unwrap(), b"v1");
+        assert_eq!(table.get(Secs(1_600_000_000).to_key()).unwrap().unwrap(), b"v1");
+        table.delete_at(Secs(1_600_000_000)).unwrap();
+        assert!(table.get_at(Secs(1_600_000_000)).unwrap().is_none());
+    })
+}
+
+#[test]
+fn test_get_between() {
+    run_test("test_get_between", |db| {
+        let name = "huobi.btc.usdt.1min";
+        let table = db.new_table(name).unwrap();
+        // 255 and 256 differ in their lowest byte only; 65536 spans a byte boundary.
+        for ts in &[65536, 255, 256, 60, 120] {
+            table.put_at(Millis(*ts), ts.to_string()).unwrap();
+        }
+        let result = table.get_between(Millis(60), Millis(65536)).unwrap();
+        let result: Vec<u64> = result.into_iter().map(|(ts, _)| ts.0).collect();
+        assert_eq!(result, vec![60, 120, 255, 256]);
+        assert_eq!(table.get_latest().unwrap(), Some((Millis(65536), b"65536".to_vec())));
+    })
+}
+
+#[cfg(test)]
+fn collect_secs(table: &Table) -> Vec<u64> {
+    table.get_between(Secs(0), Secs(u64::MAX)).unwrap().into_iter().map(|(ts, _)| ts.0).collect()
+}
+
+#[test]
+fn test_delete_before() {
+    run_test("test_delete_before", |db| {
+        let table = db.new_table("huobi.btc.usdt.1min").unwrap();
+        for ts in &[60, 120, 180, 240] {
+            table.put_at(Secs(*ts), b"v").unwrap();
+        }
+        let sn = db.get_latest_sn();
+        table.delete_before(Secs(180), false).unwrap();
+        assert_eq!(collect_secs(&table), vec![180, 240]);
+        let batch = db.get_updates_since(sn).unwrap().next().unwrap().unwrap();
+        assert!(batch.updates.iter().any(|update| matches!(
+            update,
+            crate::update::Update::DeleteRange { from_key, to_key }
+                if from_key[..] == table.id()
+                    && to_key[..] == build_inner_key(table.id(), Secs(180).to_key())
+        )));
+        table.delete_before(Secs(240), true).unwrap();
+        assert_eq!(collect_secs(&table), vec![240]);
+    })
+}
+
+#[test]
+fn test_delete_between() {
+    run_test("test_delete_between", |db| {
+        let table = db.new_table("huobi.btc.usdt.1min").unwrap();
+        for ts in &[60, 120, 180, 240] {
+            table.put_at(Millis(*ts), b"v").unwrap();
+        }
+        table.delete_between(Millis(120), Millis(240), true).unwrap();
+        let result = table.get_between(Millis(0), Millis(u64::MAX)).unwrap();
+        let result: Vec<u64> = result.into_iter().map(|(ts, _)| ts.0).collect();
+        assert_eq!(result, vec![60, 240]);
+    })
+}
+
+#[test]
+fn test_keep_last() {
+    run_test("test_keep_last", |db| {
+        let table = db.new_table("huobi.btc.usdt.1min").unwrap();
+        let next_table = db.new_table("huobi.btc.usdt.5min").unwrap();
+        for ts in &[60, 120, 180, 240] {
+            table.put_at(Secs(*ts), b"v").unwrap();
+            next_table.put_at(Secs(*ts), b"v").unwrap();
+        }
+        table.keep_last(10, false).unwrap();
+        assert_eq!(collect_secs(&table), vec![60, 120, 180, 240]);
+        table.keep_last(2, false).unwrap();
+        assert_eq!(collect_secs(&table), vec![180, 240]);
+        table.keep_last(1, true).unwrap();
+        assert_eq!(collect_secs(&table), vec![240]);
+        table.keep_last(0, true).unwrap();
+        assert!(collect_secs(&table).is_empty());
+        assert_eq!(collect_secs(&next_table), vec![60, 120, 180, 240]);
+    })
+}
@@ -0,0 +1,147 @@
+use crate::db::decode_table_name;
+#[cfg(test)]
+use crate::db::Db;
+use crate::types::*;
+use crate::update::Update;
+use crate::update_batch::UpdateBatch;
+use crate::update_iterator::UpdateIterator;
+use crate::utils::*;
+use crate::Error;
+use std::collections::HashSet;
+
+/// Selects tables by id or by name prefix.
+#[derive(Clone, Debug, Default)]
+pub struct TableFilter {
+    pub(in crate) ids: Vec<TableId>,
+    pub(in crate) prefixes: Vec<String>,
+}
+
+impl TableFilter {
+    #[inline]
+    pub fn new() -> Self {
+        TableFilter::default()
+    }
+
+    #[inline]
+    pub fn add_id(&mut self, id: TableId) {
+        self.ids.push(id);
+    }
+
+    /// Selects the tables whose names start with `prefix`, including the ones created or renamed
+    /// into it later.
+    #[inline]
+    pub fn add_prefix(&mut self, prefix: &str) {
+        self.prefixes.push(prefix.to_owned());
+    }
+
+    #[inline]
+    pub(in crate) fn matches_name(&self, name: &str) -> bool {
+        self.prefixes.iter().any(|prefix| name.starts_with(prefix.as_str()))
+    }
+}
+
+/// Yields every batch of the change feed with only the updates of the selected tables, so that
+/// the sequence numbers of batches without any can still be committed.
+pub struct FilteredUpdateIterator {
+    inner: UpdateIterator,
+    filter: TableFilter,
+    // The selected tables, following the id-to-name table through the feed.
+    ids: HashSet<TableId>,
+}
+
+impl FilteredUpdateIterator {
+    #[inline]
+    pub(in crate) fn new(inner: UpdateIterator, filter: TableFilter, ids: HashSet<TableId>) -> Self {
+        FilteredUpdateIterator { inner, filter, ids }
+    }
+
+    fn filter(&mut self, mut batch: UpdateBatch) -> Result<UpdateBatch, Error> {
+        let mut renamed = vec![];
+        for update in &batch.updates {
+            match update {
+                Update::Put { key, value } if is_id_to_name_table_inner_key(key) => {
+                    let name = decode_table_name(value)?;
+                    renamed.push((u8s_to_table_id(extract_key(key)), Some(name)));
+                }
+                Update::Delete { key } if is_id_to_name_table_inner_key(key) => {
+                    renamed.push((u8s_to_table_id(extract_key(key)), None));
+                }
+                _ => {}
+            }
+        }
+        let mut updates = Vec::with_capacity(batch.updates.len());
+        for update in batch.updates {
+            if self.ids.contains(&update.table_id()?) {
+                updates.push(update);
+            }
+        }
+        batch.updates = updates;
+        // Catalog changes take effect after the batch, so that destroying a table is reported.
+        for (id, name) in renamed {
+            if self.filter.ids.contains(&id) {
+                continue;
+            }
+            match name {
+                Some(name) if self.filter.matches_name(&name) => self.ids.insert(id),
+                _ => self.ids.remove(&id),
+            };
+        }
+        Ok(batch)
+    }
+}
+
+impl Iterator for FilteredUpdateIterator {
+    type Item = Result<UpdateBatch, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let batch = self.inner.next()?;
+        Some(batch.and_then(|batch| self.filter(batch)))
+    }
+}
+
+#[test]
+fn test_get_updates_since_for() {
+    run_test("test_get_updates_since_for", |db| {
+        let btc_1m = db.new_table("huobi.btc.usdt.1m").unwrap();
+        let eth_1m = db.new_table("huobi.eth.usdt.1m").unwrap();
+        let binance = db.new_table("binance.btc.usdt.1m").unwrap();
+        let sn = db.get_latest_sn();
+        let mut filter = TableFilter::new();
+        filter.add_prefix("huobi.btc.");
+        filter.add_id(binance.id());
+        // The iterator reads the writes made after it was created.
+        let filtered = db.get_updates_since_for(sn, &filter).unwrap();
+        btc_1m.put(b"k1", b"v1").unwrap();
+        eth_1m.put(b"k1", b"v1").unwrap();
+        let mut batch = Db::batch_x();
+        batch.put(btc_1m.id(), b"k2", b"v2");
+        batch.put(eth_1m.id(), b"k2", b"v2");
+        batch.put(binance.id(), b"k2", b"v2");
+        db.write(batch).unwrap();
+        let btc_5m = db.new_table("huobi.btc.usdt.5m").unwrap();
+        btc_5m.put(b"k1", b"v1").unwrap();
+        db.rename_table("huobi.btc.usdt.1m", "huobi.xbt.usdt.1m").unwrap();
+        btc_1m.put(b"k3", b"v3").unwrap();
+        let all: Vec<UpdateBatch> = db.get_updates_since(sn).unwrap().map(Result::unwrap).collect();
+        let filtered: Vec<UpdateBatch> = filtered.map(Result::unwrap).collect();
+        let sns = |batches: &[UpdateBatch]| batches.iter().map(|b| b.sn).collect::<Vec<u64>>();
+        assert_eq!(sns(&filtered), sns(&all));
+        let table_ids = |batch: &UpdateBatch| {
+            batch.updates.iter().map(|u| u.table_id().unwrap()).collect::<Vec<TableId>>()
+        };
+        assert_eq!(table_ids(&filtered[0]), vec![btc_1m.id()]);
+        assert!(filtered[1].updates.is_empty());
+        assert_eq!(table_ids(&filtered[2]), vec![btc_1m.id(), binance.id()]);
+        assert!(filtered[3].updates.is_empty());
+        assert_eq!(table_ids(&filtered[4]), vec![btc_5m.id()]);
+        assert!(filtered[5].updates.is_empty());
+        assert!(filtered[6].updates.is_empty());
+        // Tables renamed since are selected by their names as of `sn`.
+        let replayed: Vec<UpdateBatch> =
+            db.get_updates_since_for(sn, &filter).unwrap().map(Result::unwrap).collect();
+        assert_eq!(
+            replayed.iter().map(table_ids).collect::<Vec<_>>(),
+            filtered.iter().map(table_ids).collect::<Vec<_>>()
+        );
+    })
+}
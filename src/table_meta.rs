@@ -0,0 +1,24 @@
+use crate::timestamp::Millis;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Metadata attached to a table, kept across renames and removed with the table.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TableMeta {
+    pub created_at: Option<Millis>,
+    pub description: Option<String>,
+    /// The name of the `KeyCodec` the keys are encoded with, e.g. "u64".
+    pub key_codec: Option<String>,
+    pub value_schema: Option<String>,
+    /// How long entries are meant to be kept.
+    pub retention: Option<Duration>,
+    pub owner: Option<String>,
+}
+
+impl TableMeta {
+    /// Returns empty metadata, `created_at` is recorded when the table is created.
+    #[inline]
+    pub fn new() -> Self {
+        TableMeta::default()
+    }
+}
@@ -0,0 +1,129 @@
+use crate::consts::*;
+use crate::types::*;
+#[cfg(test)]
+use crate::utils::*;
+use bytes::Bytes;
+use rocksdb::LiveFile;
+
+/// Size and cardinality of a table.
+///
+/// Size and key count are estimated from the metadata of the sst files the table overlaps, so
+/// they don't cover writes still in the memtables, and partially overlapped files are counted in
+/// proportion to how much of their key range the table covers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableStats {
+    pub name: String,
+    pub id: TableId,
+    pub approximate_size: u64,
+    pub approximate_num_keys: u64,
+    pub first_key: Option<Bytes>,
+    pub last_key: Option<Bytes>,
+}
+
+/// Estimates the size and the number of live keys of `files` in `[from, to)`.
+pub(in crate) fn estimate_range(files: &[LiveFile], from: &[u8], to: &[u8]) -> (u64, u64) {
+    let mut size = 0.0;
+    let mut num_keys = 0.0;
+    for file in files {
+        let (start, end) = match (&file.start_key, &file.end_key) {
+            (Some(start), Some(end)) => (start.as_slice(), end.as_slice()),
+            _ => continue,
+        };
+        if end < from || start >= to {
+            continue;
+        }
+        // System tables hold few keys, interpolating over their ids would make the userland
+        // tables sharing a file with them look much smaller than they are.
+        let start = start.max(&MIN_USERLAND_TABLE_ID[..]);
+        let fraction = overlap_fraction(start, end, from, to);
+        size += file.size as f64 * fraction;
+        num_keys += file.num_entries.saturating_sub(file.num_deletions) as f64 * fraction;
+    }
+    (size.round() as u64, num_keys.round() as u64)
+}
+
+/// Returns which part of the file key range `[start, end]` falls in `[from, to)`, assuming keys
+/// are spread evenly over it.
+fn overlap_fraction(start: &[u8], end: &[u8], from: &[u8], to: &[u8]) -> f64 {
+    if from <= start && end < to {
+        return 1.0;
+    }
+    let common_len = start.iter().zip(end).take_while(|(a, b)| a == b).count();
+    let position = |key: &[u8]| -> f64 {
+        if key <= start {
+            return 0.0;
+        }
+        if key >= end {
+            return 1.0;
+        }
+        let (start, end, key) =
+            (to_f64(start, common_len), to_f64(end, common_len), to_f64(key, common_len));
+        if end > start {
+            ((key - start) / (end - start)).clamp(0.0, 1.0)
+        } else {
+            0.5
+        }
+    };
+    (position(to) - position(from)).max(0.0)
+}
+
+/// Reads the 8 bytes after `offset` as a big-endian number, padding short keys with zeros.
+#[inline]
+fn to_f64(key: &[u8], offset: usize) -> f64 {
+    let mut buf = [0; 8];
+    if offset < key.len() {
+        let len = (key.len() - offset).min(8);
+        buf[..len].copy_from_slice(&key[offset..offset + len]);
+    }
+    u64::from_be_bytes(buf) as f64
+}
+
+#[test]
+fn test_overlap_fraction() {
+    assert_eq!(
+        overlap_fraction(&[0, 0, 4, 0], &[0, 0, 4, 0, 9], &[0, 0, 4, 0], &[0, 0, 4, 1]),
+        1.0
+    );
+    let fraction = overlap_fraction(&[0, 0, 4, 0], &[0, 0, 6, 0], &[0, 0, 4, 0], &[0, 0, 5, 0]);
+    assert!((fraction - 0.5).abs() < 1e-9);
+    let fraction = overlap_fraction(&[0, 0, 4, 0], &[0, 0, 6, 0], &[0, 0, 7, 0], &[0, 0, 8, 0]);
+    assert_eq!(fraction, 0.0);
+}
+
+#[test]
+fn test_table_stats() {
+    run_test("test_table_stats", |db| {
+        let table_1m = db.new_table("huobi.btc.usdt.1m").unwrap();
+        let table_5m = db.new_table("huobi.btc.usdt.5m").unwrap();
+        for i in 0..1000u32 {
+            table_1m.put(i.to_be_bytes(), [0; 100]).unwrap();
+        }
+        for i in 0..10u32 {
+            table_5m.put(i.to_be_bytes(), [0; 100]).unwrap();
+        }
+        db.flush().unwrap();
+        let stats = db.table_stats("huobi.btc.usdt.1m").unwrap();
+        assert_eq!(stats.id, table_1m.id());
+        assert!(stats.approximate_num_keys > 900 && stats.approximate_num_keys < 1100);
+        assert!(stats.approximate_size > 10_000);
+        assert_eq!(stats.first_key.unwrap(), Bytes::copy_from_slice(&0u32.to_be_bytes()));
+        assert_eq!(stats.last_key.unwrap(), Bytes::copy_from_slice(&999u32.to_be_bytes()));
+        let all_stats = db.all_table_stats().unwrap();
+        assert_eq!(all_stats.len(), 2);
+        assert_eq!(all_stats[0].name, "huobi.btc.usdt.1m");
+        assert!(all_stats[1].approximate_num_keys <= 20);
+        assert!(all_stats[1].approximate_size < stats.approximate_size);
+    })
+}
+
+#[test]
+fn test_table_stats_of_empty_table() {
+    run_test("test_table_stats_of_empty_table", |db| {
+        db.new_table("huobi.btc.usdt.1m").unwrap();
+        let stats = db.table_stats("huobi.btc.usdt.1m").unwrap();
+        assert_eq!(stats.approximate_size, 0);
+        assert_eq!(stats.approximate_num_keys, 0);
+        assert!(stats.first_key.is_none() && stats.last_key.is_none());
+        assert!(db.table_stats("huobi.btc.usdt.5m").is_err());
+    })
+}
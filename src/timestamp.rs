@@ -0,0 +1,136 @@
+use byteorder::{BigEndian, ByteOrder};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const TIMESTAMP_KEY_LEN: usize = 8;
+
+/// A point in time usable as a table key.
+///
+/// Keys are encoded as big-endian u64, so byte order in rocksdb matches
+/// chronological order as long as a table sticks to one precision.
+///
+/// Conversions saturate at `u64::MAX` instead of wrapping, which `Nanos`
+/// reaches in 2554 and `Millis` long after.
+pub trait Timestamp: Copy + Ord {
+    fn to_key(self) -> [u8; TIMESTAMP_KEY_LEN];
+
+    fn from_key(key: &[u8]) -> Option<Self>;
+}
+
+macro_rules! impl_timestamp {
+    ($name:ident, $from_duration:expr, $to_duration:expr) => {
+        #[derive(
+            Clone,
+            Copy,
+            Debug,
+            Default,
+            PartialEq,
+            Eq,
+            PartialOrd,
+            Ord,
+            Hash,
+            Serialize,
+            Deserialize,
+        )]
+        pub struct $name(pub u64);
+
+        impl $name {
+            #[inline]
+            pub fn now() -> Self {
+                Self::from(SystemTime::now())
+            }
+
+            /// Returns None if the time doesn't fit in a `SystemTime`.
+            #[inline]
+            pub fn to_system_time(self) -> Option<SystemTime> {
+                UNIX_EPOCH.checked_add($to_duration(self.0))
+            }
+        }
+
+        impl Timestamp for $name {
+            #[inline]
+            fn to_key(self) -> [u8; TIMESTAMP_KEY_LEN] {
+                let mut buf = [0; TIMESTAMP_KEY_LEN];
+                BigEndian::write_u64(&mut buf, self.0);
+                buf
+            }
+
+            #[inline]
+            fn from_key(key: &[u8]) -> Option<Self> {
+                if key.len() == TIMESTAMP_KEY_LEN {
+                    Some($name(BigEndian::read_u64(key)))
+                } else {
+                    None
+                }
+            }
+        }
+
+        impl From<SystemTime> for $name {
+            /// Times before the epoch map to 0 and times past the range to `u64::MAX`.
+            #[inline]
+            fn from(time: SystemTime) -> Self {
+                let duration = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+                $name(u64::try_from($from_duration(duration)).unwrap_or(u64::MAX))
+            }
+        }
+    };
+}
+
+impl_timestamp!(Secs, |d: Duration| d.as_secs(), Duration::from_secs);
+impl_timestamp!(Millis, |d: Duration| d.as_millis(), Duration::from_millis);
+impl_timestamp!(Nanos, |d: Duration| d.as_nanos(), Duration::from_nanos);
+
+impl From<Secs> for Millis {
+    #[inline]
+    fn from(ts: Secs) -> Self {
+        Millis(ts.0.saturating_mul(1_000))
+    }
+}
+
+impl From<Secs> for Nanos {
+    #[inline]
+    fn from(ts: Secs) -> Self {
+        Nanos(ts.0.saturating_mul(1_000_000_000))
+    }
+}
+
+impl From<Millis> for Nanos {
+    #[inline]
+    fn from(ts: Millis) -> Self {
+        Nanos(ts.0.saturating_mul(1_000_000))
+    }
+}
+
+#[test]
+fn test_to_key() {
+    assert_eq!(Secs(1).to_key(), [0, 0, 0, 0, 0, 0, 0, 1]);
+    assert_eq!(Millis(256).to_key(), [0, 0, 0, 0, 0, 0, 1, 0]);
+    assert!(Nanos(255).to_key() < Nanos(256).to_key());
+}
+
+#[test]
+fn test_from_key() {
+    assert_eq!(Secs::from_key(&[0, 0, 0, 0, 0, 0, 1, 0]), Some(Secs(256)));
+    assert_eq!(Secs::from_key(b"k111"), None);
+}
+
+#[test]
+fn test_conversion() {
+    let time = UNIX_EPOCH + Duration::from_millis(1_500);
+    assert_eq!(Secs::from(time), Secs(1));
+    assert_eq!(Millis::from(time), Millis(1_500));
+    assert_eq!(Nanos::from(Millis::from(time)), Nanos(1_500_000_000));
+    assert_eq!(Millis(1_500).to_system_time(), Some(time));
+}
+
+#[test]
+fn test_conversion_saturates() {
+    let time = UNIX_EPOCH + Duration::from_secs(u64::MAX / 1_000);
+    assert_eq!(Nanos::from(time), Nanos(u64::MAX));
+    assert_eq!(Nanos::from(Secs(u64::MAX / 1_000)), Nanos(u64::MAX));
+    assert_eq!(Millis::from(Secs(u64::MAX)), Millis(u64::MAX));
+    assert_eq!(Nanos::from(Millis(u64::MAX / 1_000)), Nanos(u64::MAX));
+    assert_eq!(Secs(u64::MAX).to_system_time(), None);
+    assert!(Nanos(u64::MAX).to_system_time().is_some());
+}
@@ -0,0 +1,215 @@
+use crate::batch::Batch;
+use crate::codec::*;
+use crate::entry_cursor::EntryCursor;
+use crate::table::Table;
+#[cfg(test)]
+use crate::utils::*;
+use crate::Error;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::marker::PhantomData;
+
+/// A table whose keys are encoded with `KeyCodec` and values with serde.
+pub struct TypedTable<'a, K, V> {
+    table: Table<'a>,
+    phantom: PhantomData<fn() -> (K, V)>,
+}
+
+impl<'a, K, V> Clone for TypedTable<'a, K, V> {
+    fn clone(&self) -> Self {
+        TypedTable { table: self.table.clone(), phantom: PhantomData }
+    }
+}
+
+impl<'a, K, V> TypedTable<'a, K, V>
+where
+    K: KeyCodec,
+    V: Serialize + DeserializeOwned,
+{
+    #[inline]
+    pub fn new(table: Table<'a>) -> Self {
+        TypedTable { table, phantom: PhantomData }
+    }
+
+    #[inline]
+    pub fn put(&self, key: &K, value: &V) -> Result<(), Error> {
+        self.table.put(key.encode_key(), encode_value(value)?)
+    }
+
+    #[inline]
+    pub fn batch(&self) -> TypedBatch<K, V> {
+        TypedBatch::new(self.table.batch())
+    }
+
+    #[inline]
+    pub fn write(&self, b: TypedBatch<K, V>) -> Result<(), Error> {
+        self.table.write(b.inner)
+    }
+
+    #[inline]
+    pub fn delete(&self, key: &K) -> Result<(), Error> {
+        self.table.delete(key.encode_key())
+    }
+
+    #[inline]
+    pub fn get(&self, key: &K) -> Result<Option<V>, Error> {
+        match self.table.get(key.encode_key())? {
+            Some(value) => Ok(Some(decode_value(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    #[inline]
+    pub fn cursor(&self) -> TypedCursor<'_, K, V> {
+        TypedCursor::new(self.table.cursor())
+    }
+
+    #[inline]
+    pub fn table(&self) -> &Table<'a> {
+        &self.table
+    }
+}
+
+pub struct TypedBatch<K, V> {
+    inner: Batch,
+    phantom: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V> TypedBatch<K, V>
+where
+    K: KeyCodec,
+    V: Serialize,
+{
+    #[inline]
+    fn new(inner: Batch) -> Self {
+        TypedBatch { inner, phantom: PhantomData }
+    }
+
+    #[inline]
+    pub fn put(&mut self, key: &K, value: &V) -> Result<(), Error> {
+        self.inner.put(key.encode_key(), encode_value(value)?);
+        Ok(())
+    }
+
+    #[inline]
+    pub fn delete(&mut self, key: &K) {
+        self.inner.delete(key.encode_key())
+    }
+
+    #[inline]
+    pub fn delete_range(&mut self, from_key: &K, to_key: &K) {
+        self.inner.delete_range(from_key.encode_key(), to_key.encode_key())
+    }
+}
+
+pub struct TypedCursor<'a, K, V> {
+    inner: EntryCursor<'a>,
+    phantom: PhantomData<fn() -> (K, V)>,
+}
+
+impl<'a, K, V> TypedCursor<'a, K, V>
+where
+    K: KeyCodec,
+    V: DeserializeOwned,
+{
+    #[inline]
+    fn new(inner: EntryCursor<'a>) -> Self {
+        TypedCursor { inner, phantom: PhantomData }
+    }
+
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        self.inner.is_valid()
+    }
+
+    #[inline]
+    pub fn seek_to_first(&mut self) {
+        self.inner.seek_to_first()
+    }
+
+    #[inline]
+    pub fn seek_to_last(&mut self) {
+        self.inner.seek_to_last()
+    }
+
+    #[inline]
+    pub fn seek(&mut self, key: &K) {
+        self.inner.seek(key.encode_key())
+    }
+
+    #[inline]
+    pub fn seek_for_prev(&mut self, key: &K) {
+        self.inner.seek_for_prev(key.encode_key())
+    }
+
+    #[inline]
+    pub fn next(&mut self) {
+        self.inner.next()
+    }
+
+    #[inline]
+    pub fn prev(&mut self) {
+        self.inner.prev()
+    }
+
+    #[inline]
+    pub fn key(&self) -> Result<Option<K>, Error> {
+        match self.inner.key() {
+            Some(key) => Ok(Some(K::decode_key(key)?)),
+            None => Ok(None),
+        }
+    }
+
+    #[inline]
+    pub fn value(&self) -> Result<Option<V>, Error> {
+        match self.inner.value() {
+            Some(value) => Ok(Some(decode_value(value)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[test]
+fn test_put_and_get() {
+    run_test("test_typed_put_and_get", |db| {
+        let table = db.new_typed_table::<u64, (String, f64)>("huobi.btc.usdt.1m").unwrap();
+        let value = ("huobi".to_owned(), 1.5);
+        table.put(&1024, &value).unwrap();
+        assert_eq!(table.get(&1024).unwrap(), Some(value));
+        table.delete(&1024).unwrap();
+        assert_eq!(table.get(&1024).unwrap(), None);
+    })
+}
+
+#[test]
+fn test_codec_error() {
+    run_test("test_typed_codec_error", |db| {
+        let table = db.new_typed_table::<u64, (String, f64)>("huobi.btc.usdt.1m").unwrap();
+        table.table().put(1024u64.encode_key(), b"\xc1").unwrap();
+        assert!(matches!(table.get(&1024), Err(Error::Codec(_))));
+    })
+}
+
+#[test]
+fn test_batch_and_cursor() {
+    run_test("test_typed_batch_and_cursor", |db| {
+        let table = db.new_typed_table::<u64, String>("huobi.btc.usdt.1m").unwrap();
+        let mut batch = table.batch();
+        for key in &[256u64, 255, 1, 65536] {
+            batch.put(key, &key.to_string()).unwrap();
+        }
+        batch.delete(&1);
+        table.write(batch).unwrap();
+        let mut cursor = table.cursor();
+        cursor.seek(&2);
+        let mut result = vec![];
+        while cursor.is_valid() {
+            result.push((cursor.key().unwrap().unwrap(), cursor.value().unwrap().unwrap()));
+            cursor.next();
+        }
+        assert_eq!(
+            result,
+            vec![(255, "255".to_owned()), (256, "256".to_owned()), (65536, "65536".to_owned())]
+        );
+    })
+}
@@ -1,10 +1,27 @@
+use crate::consts::*;
+use crate::types::*;
+use crate::utils::*;
+use crate::Error;
 use bytes::Bytes;
+use serde::{Deserialize, Serialize};
 use std::fmt::{Debug, Formatter, Result as FmtResult};
 
+/// A raw update, whose keys are inner keys led by the table id.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Update {
-    Put { key: Bytes, value: Bytes },
-    Delete { key: Bytes },
-    DeleteRange { from_key: Bytes, to_key: Bytes },
+    Put {
+        key: Bytes,
+        value: Bytes,
+    },
+    Delete {
+        key: Bytes,
+    },
+    /// Deletes `[from_key, to_key)`, where `to_key` may belong to the next table when the
+    /// whole table is cleared.
+    DeleteRange {
+        from_key: Bytes,
+        to_key: Bytes,
+    },
 }
 
 impl Debug for Update {
@@ -18,3 +35,18 @@ impl Debug for Update {
         }
     }
 }
+
+impl Update {
+    /// Returns the id of the table the update belongs to.
+    #[inline]
+    pub fn table_id(&self) -> Result<TableId, Error> {
+        let key = match self {
+            Update::Put { key, .. } | Update::Delete { key } => key,
+            Update::DeleteRange { from_key, .. } => from_key,
+        };
+        if key.len() < TABLE_ID_LEN {
+            return Err(Error::corruption(format!("Invalid inner key {:?}", key)));
+        }
+        Ok(extract_table_id(key))
+    }
+}
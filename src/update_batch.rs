@@ -1,29 +1,64 @@
 use crate::consts::*;
 use crate::update::Update;
 use crate::utils::*;
+use crate::Error;
 use bytes::Bytes;
 use rocksdb::WriteBatchIterator;
+use serde::{Deserialize, Serialize};
 use std::fmt::{Debug, Formatter, Result as FmtResult};
 
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct UpdateBatch {
     pub sn: u64,
     pub updates: Vec<Update>,
 }
 
-impl WriteBatchIterator for UpdateBatch {
+/// Collects the updates of a raw write batch, remembering the first one that fails to decode.
+pub(in crate) struct UpdateBatchBuilder {
+    batch: UpdateBatch,
+    error: Option<Error>,
+}
+
+impl UpdateBatchBuilder {
+    pub(in crate) fn new(sn: u64) -> Self {
+        UpdateBatchBuilder { batch: UpdateBatch { sn, updates: vec![] }, error: None }
+    }
+
+    pub(in crate) fn build(self) -> Result<UpdateBatch, Error> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.batch),
+        }
+    }
+}
+
+impl WriteBatchIterator for UpdateBatchBuilder {
     fn put(&mut self, key: Box<[u8]>, value: Box<[u8]>) {
-        self.updates.push(Update::Put {
+        self.batch.updates.push(Update::Put {
             key: Bytes::copy_from_slice(key.as_ref()),
             value: Bytes::copy_from_slice(value.as_ref()),
         })
     }
     fn delete(&mut self, key: Box<[u8]>) {
-        let table_id = extract_table_id(&key);
-        if table_id == DELETE_RANGE_HINT_TABLE_ID {
-            let (from_key, to_key) = extract_delete_range_hint(key);
-            self.updates.push(Update::DeleteRange { from_key, to_key })
+        let hint = if key.len() < TABLE_ID_LEN {
+            Err(Error::corruption(format!("Invalid inner key {:?}", key)))
+        } else if extract_table_id(&key) == DELETE_RANGE_HINT_TABLE_ID {
+            extract_delete_range_hint(&key)
         } else {
-            self.updates.push(Update::Delete { key: Bytes::copy_from_slice(key.as_ref()) })
+            Ok(None)
+        };
+        match hint {
+            Ok(Some((from_key, to_key))) => {
+                self.batch.updates.push(Update::DeleteRange { from_key, to_key })
+            }
+            // Hints of unknown versions are passed on as they are.
+            Ok(None) => self
+                .batch
+                .updates
+                .push(Update::Delete { key: Bytes::copy_from_slice(key.as_ref()) }),
+            Err(e) => {
+                self.error.get_or_insert(e);
+            }
         }
     }
 }
@@ -34,8 +69,21 @@ impl Debug for UpdateBatch {
     }
 }
 
+impl Default for UpdateBatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl UpdateBatch {
     pub fn new() -> Self {
         UpdateBatch { sn: 0, updates: vec![] }
     }
 }
+
+#[test]
+fn test_delete_with_short_key() {
+    let mut builder = UpdateBatchBuilder::new(1);
+    builder.delete(Box::new([0, 0, 3]));
+    assert!(matches!(builder.build(), Err(Error::Corruption(_))));
+}
@@ -1,28 +1,208 @@
-use crate::update_batch::UpdateBatch;
+use crate::update_batch::{UpdateBatch, UpdateBatchBuilder};
+#[cfg(test)]
+use crate::utils::*;
+use crate::wal::earliest_wal_sn;
+use crate::Error;
+#[cfg(test)]
+use crate::{db::Db, options::Options};
 use rocksdb::DBWALIterator;
+use std::path::{Path, PathBuf};
 
 pub struct UpdateIterator {
     inner: DBWALIterator,
+    // Where the WAL lives, to tell the gaps apart from other failures.
+    db_path: Option<PathBuf>,
+    // The sequence number the next batch must start at, if known.
+    next_sn: Option<u64>,
+    failed: bool,
 }
 
 impl Iterator for UpdateIterator {
-    type Item = UpdateBatch;
+    type Item = Result<UpdateBatch, Error>;
     fn next(&mut self) -> Option<Self::Item> {
-        let result = self.inner.next();
-        if result.is_none() {
-            None
-        } else {
-            let (sn, b) = result.unwrap();
-            let mut ub = UpdateBatch::new();
-            ub.sn = sn;
-            b.iterate(&mut ub);
-            Some(ub)
+        if self.failed {
+            return None;
         }
+        let (sn, b) = match self.inner.next() {
+            Some(item) => item,
+            None => return self.check_status().err().map(Err),
+        };
+        if let Some(expected) = self.next_sn.filter(|&expected| expected != sn) {
+            self.failed = true;
+            return Some(Err(Error::WalGap { expected, found: Some(sn) }));
+        }
+        self.next_sn = Some(sn + b.len() as u64);
+        let mut builder = UpdateBatchBuilder::new(sn);
+        b.iterate(&mut builder);
+        Some(builder.build())
     }
 }
 
 impl UpdateIterator {
     pub fn new(inner: DBWALIterator) -> Self {
-        UpdateIterator { inner }
+        UpdateIterator { inner, db_path: None, next_sn: None, failed: false }
+    }
+
+    /// Reads the WAL of the database at `db_path`, checking that the first batch starts at
+    /// `next_sn`.
+    #[inline]
+    pub(in crate) fn with_next_sn(inner: DBWALIterator, db_path: &Path, next_sn: Option<u64>) -> Self {
+        UpdateIterator { inner, db_path: Some(db_path.to_owned()), next_sn, failed: false }
+    }
+
+    #[inline]
+    pub(in crate) fn next_sn(&self) -> Option<u64> {
+        self.next_sn
+    }
+
+    /// Reaching the end of the WAL is fine, even if rocksdb asks for a new iterator to read
+    /// further. Other failures are reported as gaps if the WAL no longer holds the next batch,
+    /// as rocksdb stops at gaps instead of skipping them.
+    fn check_status(&mut self) -> Result<(), Error> {
+        let e = match self.inner.status() {
+            Err(e) if !is_try_again(&e) => e,
+            _ => return Ok(()),
+        };
+        self.failed = true;
+        let earliest_sn = match &self.db_path {
+            Some(db_path) => earliest_wal_sn(db_path)?,
+            None => None,
+        };
+        match (self.next_sn, earliest_sn) {
+            (Some(expected), Some(found)) if found > expected => {
+                Err(Error::WalGap { expected, found: Some(found) })
+            }
+            _ => Err(Error::Storage(e)),
+        }
     }
 }
+
+/// Tells whether rocksdb asks for a new iterator, which it does once writes land after the end of
+/// the WAL was read. The C API only exposes the status text, "Operation failed. Try again." as
+/// rendered by `Status::ToString` in rocksdb 6.11 (librocksdb-sys 6.11.4), pinned by
+/// `test_try_again_status`.
+fn is_try_again(e: &rocksdb::Error) -> bool {
+    e.as_ref().starts_with("Operation failed. Try again.")
+}
+
+#[cfg(test)]
+fn build_wal_test_db(path: &str, opts: &Options) -> (Db, u64) {
+    let _ = Db::destroy(path);
+    let db = Db::new(path, opts).unwrap();
+    let table = db.new_table("huobi.btc.usdt.1m").unwrap();
+    table.put(b"k1", b"v1").unwrap();
+    let sn = db.get_latest_sn();
+    table.put(b"k2", b"v2").unwrap();
+    db.flush().unwrap();
+    for key in &["k3", "k4", "k5"] {
+        table.put(key, b"v").unwrap();
+    }
+    (db, sn)
+}
+
+#[test]
+fn test_wal_gap() {
+    let path = "./data/test_wal_gap";
+    let (db, sn) = build_wal_test_db(path, &Options::new());
+    // The obsolete WAL is deleted in the background after the flush.
+    let mut result = db.get_updates_since(sn).map(|_| ());
+    for _ in 0..100 {
+        if result.is_err() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        result = db.get_updates_since(sn).map(|_| ());
+    }
+    assert!(matches!(result, Err(Error::WalGap { expected, .. }) if expected == sn));
+    assert_eq!(db.get_updates_since(db.get_latest_sn() - 1).unwrap().count(), 1);
+    drop(db);
+    Db::destroy(path).unwrap();
+}
+
+#[test]
+fn test_wal_retention() {
+    let path = "./data/test_wal_retention";
+    let mut opts = Options::new();
+    opts.set_wal_ttl_seconds(3600);
+    opts.set_wal_size_limit_mb(64);
+    let (db, sn) = build_wal_test_db(path, &opts);
+    let batches: Vec<UpdateBatch> = db.get_updates_since(sn).unwrap().map(Result::unwrap).collect();
+    assert_eq!(batches.len(), 4);
+    assert_eq!(batches[0].sn, sn + 1);
+    assert_eq!(batches[3].sn, db.get_latest_sn());
+    drop(db);
+    Db::destroy(path).unwrap();
+}
+
+#[test]
+fn test_get_updates_since_inside_batch() {
+    run_test("test_get_updates_since_inside_batch", |db| {
+        let table = db.new_table("huobi.btc.usdt.1m").unwrap();
+        let mut batch = table.batch();
+        for key in &["k1", "k2", "k3", "k4", "k5"] {
+            batch.put(key, b"v");
+        }
+        table.write(batch).unwrap();
+        let last_sn = db.get_latest_sn();
+        table.put(b"k6", b"v6").unwrap();
+        for sn in last_sn - 4..=last_sn {
+            let batches: Vec<UpdateBatch> =
+                db.get_updates_since(sn).unwrap().map(Result::unwrap).collect();
+            assert_eq!(batches.len(), 1);
+            assert_eq!(batches[0].sn, last_sn + 1);
+        }
+    })
+}
+
+#[test]
+fn test_get_updates_since_inside_first_batch() {
+    let path = "./data/test_get_updates_since_inside_first_batch";
+    let _ = Db::destroy(path);
+    let db = Db::new(path, &Options::new()).unwrap();
+    let table = db.new_table("huobi.btc.usdt.1m").unwrap();
+    table.put(b"k0", b"v0").unwrap();
+    db.flush().unwrap();
+    let mut batch = table.batch();
+    for key in &["k1", "k2", "k3"] {
+        batch.put(key, b"v");
+    }
+    table.write(batch).unwrap();
+    let last_sn = db.get_latest_sn();
+    table.put(b"k4", b"v4").unwrap();
+    // The obsolete WAL is deleted in the background after the flush.
+    for _ in 0..100 {
+        if earliest_wal_sn(Path::new(path)).unwrap() == Some(last_sn - 2) {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert_eq!(earliest_wal_sn(Path::new(path)).unwrap(), Some(last_sn - 2));
+    for sn in last_sn - 2..=last_sn {
+        let batches: Vec<UpdateBatch> =
+            db.get_updates_since(sn).unwrap().map(Result::unwrap).collect();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].sn, last_sn + 1);
+    }
+    assert!(matches!(
+        db.get_updates_since(last_sn - 3),
+        Err(Error::WalGap { found: Some(found), .. }) if found == last_sn - 2
+    ));
+    drop(table);
+    drop(db);
+    Db::destroy(path).unwrap();
+}
+
+#[test]
+fn test_try_again_status() {
+    run_test("test_try_again_status", |db| {
+        let table = db.new_table("huobi.btc.usdt.1m").unwrap();
+        table.put(b"k1", b"v1").unwrap();
+        let mut iter = db.inner.get_updates_since(db.get_latest_sn()).unwrap();
+        // The write goes to a WAL file created after the iterator, which it can't read.
+        db.flush().unwrap();
+        table.put(b"k2", b"v2").unwrap();
+        while iter.next().is_some() {}
+        let e = iter.status().unwrap_err();
+        assert!(is_try_again(&e), "{}", e);
+    })
+}
@@ -2,6 +2,7 @@ use crate::consts::*;
 #[cfg(test)]
 use crate::db::Db;
 use crate::types::*;
+use crate::Error;
 use byteorder::{BigEndian, ByteOrder};
 use bytes::{Bytes, BytesMut};
 
@@ -30,6 +31,11 @@ pub fn u8s_to_u32(u8s: &[u8]) -> u32 {
     BigEndian::read_u32(u8s)
 }
 
+#[inline]
+pub fn u8s_to_u64(u8s: &[u8]) -> u64 {
+    BigEndian::read_u64(u8s)
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 /// key utils
 ////////////////////////////////////////////////////////////////////////////////
@@ -38,6 +44,16 @@ pub fn build_info_table_inner_key(item_id: ItemId) -> Bytes {
     build_inner_key(INFO_TABLE_ID, item_id)
 }
 
+/// Builds the key of a per-table item in the info table.
+#[inline]
+pub fn build_info_table_item_inner_key(item_id: ItemId, table_id: TableId) -> Bytes {
+    let mut buf = BytesMut::with_capacity(TABLE_ID_LEN + item_id.len() + table_id.len());
+    buf.extend_from_slice(&INFO_TABLE_ID);
+    buf.extend_from_slice(&item_id);
+    buf.extend_from_slice(&table_id);
+    buf.freeze()
+}
+
 #[inline]
 pub fn build_name_to_id_table_inner_key<N: AsRef<[u8]>>(name: N) -> Bytes {
     build_inner_key(NAME_TO_ID_TABLE_ID, name)
@@ -48,26 +64,69 @@ pub fn build_id_to_name_table_inner_key(table_id: TableId) -> Bytes {
     build_inner_key(ID_TO_NAME_TABLE_ID, table_id)
 }
 
+#[inline]
+pub fn build_table_meta_table_inner_key(table_id: TableId) -> Bytes {
+    build_inner_key(TABLE_META_TABLE_ID, table_id)
+}
+
+#[inline]
+pub fn build_consumer_table_inner_key(name: &str) -> Bytes {
+    build_inner_key(CONSUMER_TABLE_ID, name)
+}
+
+/// Builds the hint recorded in front of a delete range, `from_key` and `to_key` are inner keys.
 #[inline]
 pub fn build_delete_range_hint_table_inner_key<F, T>(from_key: F, to_key: T) -> Bytes
 where
     F: AsRef<[u8]>,
     T: AsRef<[u8]>, {
-    let key = rmp_serde::to_vec(&(from_key.as_ref().to_vec(), to_key.as_ref().to_vec())).unwrap();
+    let (from_key, to_key) = (from_key.as_ref(), to_key.as_ref());
+    let mut key = Vec::with_capacity(5 + from_key.len() + to_key.len());
+    key.push(DELETE_RANGE_HINT_VERSION);
+    // rocksdb keys can't be longer than u32::MAX.
+    key.extend_from_slice(&(from_key.len() as u32).to_be_bytes());
+    key.extend_from_slice(from_key);
+    key.extend_from_slice(to_key);
     build_inner_key(DELETE_RANGE_HINT_TABLE_ID, key)
 }
 
+/// Returns the inner keys of a delete range hint, or `None` if the hint has an unknown version,
+/// like the ones written before hints were versioned, which only kept the user keys.
 #[inline]
-pub fn extract_delete_range_hint<K: AsRef<[u8]>>(inner_key: K) -> (Bytes, Bytes) {
+pub fn extract_delete_range_hint<K: AsRef<[u8]>>(
+    inner_key: K,
+) -> Result<Option<(Bytes, Bytes)>, Error> {
     let key = extract_key(inner_key.as_ref());
-    let (from_key, to_key): (Vec<u8>, Vec<u8>) = rmp_serde::from_slice(key).unwrap();
-    (Bytes::from(from_key), Bytes::from(to_key))
+    match key.split_first() {
+        Some((&DELETE_RANGE_HINT_VERSION, hint)) => {
+            let from_key_len = hint.get(..4).map(|len| u8s_to_u32(len) as usize);
+            match from_key_len.filter(|&len| len <= hint.len() - 4) {
+                Some(len) => {
+                    let (from_key, to_key) = hint[4..].split_at(len);
+                    Ok(Some((Bytes::copy_from_slice(from_key), Bytes::copy_from_slice(to_key))))
+                }
+                None => Err(Error::corruption(format!("Invalid delete range hint {:?}", key))),
+            }
+        }
+        Some((&MSGPACK_DELETE_RANGE_HINT_VERSION, hint)) => {
+            match rmp_serde::from_slice::<(Vec<u8>, Vec<u8>)>(hint) {
+                Ok((from_key, to_key)) => Ok(Some((Bytes::from(from_key), Bytes::from(to_key)))),
+                Err(e) => {
+                    Err(Error::corruption(format!("Invalid delete range hint {:?}: {}", key, e)))
+                }
+            }
+        }
+        _ => Ok(None),
+    }
 }
 
+/// Builds the exclusive upper bound of a table, which is the id of the next table.
+/// Every inner key of the table sorts before it regardless of the user key length. The id
+/// `u32::MAX` saturates into an empty range, no table gets it as it is above the userland ids.
 #[inline]
-#[inline]
-pub fn build_userland_table_anchor(table_id: TableId, key_len: u8) -> Bytes {
-    build_inner_key(table_id, set_every_bit_to_one((key_len + 1).into()))
+pub fn build_table_anchor(table_id: TableId) -> Bytes {
+    let next_id = table_id_to_u32(table_id).saturating_add(1);
+    Bytes::copy_from_slice(&u32_to_table_id(next_id))
 }
 
 #[inline]
@@ -93,16 +152,32 @@ pub fn extract_key(buf: &[u8]) -> &[u8] {
 }
 
 #[inline]
-fn set_every_bit_to_one(key_len: u8) -> Bytes {
-    Bytes::from(vec![255; key_len.into()])
+pub fn is_userland_table_id(table_id: TableId) -> bool {
+    (MIN_USERLAND_TABLE_ID..=MAX_USERLAND_TABLE_ID).contains(&table_id)
+}
+
+#[inline]
+pub fn is_id_to_name_table_inner_key(inner_key: &[u8]) -> bool {
+    inner_key.len() == TABLE_ID_LEN * 2 && extract_table_id(inner_key) == ID_TO_NAME_TABLE_ID
+}
+
+/// Returns the table id of a table generation key in the info table.
+#[inline]
+pub fn extract_table_generation_id(inner_key: &[u8]) -> Option<TableId> {
+    let prefix = build_info_table_inner_key(TABLE_GENERATION_ITEM_ID);
+    if inner_key.len() == prefix.len() + TABLE_ID_LEN && inner_key.starts_with(&prefix) {
+        Some(u8s_to_table_id(&inner_key[prefix.len()..]))
+    } else {
+        None
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 /// unit test utils
 ////////////////////////////////////////////////////////////////////////////////
 #[cfg(test)]
-pub(in crate) fn run_test<T>(db_name: &str, test: T) -> ()
-where T: FnOnce(Db) -> () + std::panic::UnwindSafe {
+pub(in crate) fn run_test<T>(db_name: &str, test: T)
+where T: FnOnce(Db) + std::panic::UnwindSafe {
     let mut path = String::from("./data/");
     path.push_str(db_name);
     let db = setup(&path);
@@ -153,24 +228,35 @@ fn test_build_id_to_name_table_inner_key() {
 fn test_build_delete_range_hint_table_inner_key() {
     assert_eq!(
         build_delete_range_hint_table_inner_key([0, 0, 4, 0], [0, 0, 4, 1]).as_ref(),
-        b"\0\0\0\x03\x92\x94\0\0\x04\0\x94\0\0\x04\x01"
+        b"\0\0\0\x03\x02\0\0\0\x04\0\0\x04\0\0\0\x04\x01"
     );
 }
 
 #[test]
 fn test_extract_delete_range_hint() {
-    let inner_key = b"\0\0\0\x03\x92\x94\0\0\x04\0\x94\0\0\x04\x01";
-    let (from_key, to_key) = extract_delete_range_hint(inner_key);
+    let inner_key = b"\0\0\0\x03\x02\0\0\0\x04\0\0\x04\0\0\0\x04\x01";
+    let (from_key, to_key) = extract_delete_range_hint(inner_key).unwrap().unwrap();
     assert_eq!(from_key.as_ref(), [0, 0, 4, 0]);
     assert_eq!(to_key.as_ref(), [0, 0, 4, 1]);
+    let msgpack_inner_key = b"\0\0\0\x03\x01\x92\x94\0\0\x04\0\x94\0\0\x04\x01";
+    let (from_key, to_key) = extract_delete_range_hint(msgpack_inner_key).unwrap().unwrap();
+    assert_eq!(from_key.as_ref(), [0, 0, 4, 0]);
+    assert_eq!(to_key.as_ref(), [0, 0, 4, 1]);
+    assert!(matches!(
+        extract_delete_range_hint(b"\0\0\0\x03\x02\0\0\0\x04\0"),
+        Err(Error::Corruption(_))
+    ));
+    let legacy_inner_key = b"\0\0\0\x03\x92\x94\0\0\x04\0\x94\0\0\x04\x01";
+    assert!(extract_delete_range_hint(legacy_inner_key).unwrap().is_none());
+    assert!(matches!(extract_delete_range_hint(b"\0\0\0\x03\x01\xc1"), Err(Error::Corruption(_))));
 }
 
 #[test]
-fn test_build_userland_table_anchor() {
-    assert_eq!(
-        build_userland_table_anchor([0, 0, 4, 0], 4),
-        vec![0, 0, 4, 0, 255, 255, 255, 255, 255]
-    );
+fn test_build_table_anchor() {
+    assert_eq!(build_table_anchor([0, 0, 4, 0]), vec![0, 0, 4, 1]);
+    assert_eq!(build_table_anchor([0, 0, 4, 255]), vec![0, 0, 5, 0]);
+    assert_eq!(build_table_anchor(MAX_USERLAND_TABLE_ID), vec![255, 255, 255, 255]);
+    assert_eq!(build_table_anchor([255; 4]), vec![255, 255, 255, 255]);
 }
 
 #[test]
@@ -192,3 +278,12 @@ fn test_extract_key() {
     let table_id = extract_key(&inner_key);
     assert_eq!(table_id, [0, 0, 0, 128, 0, 254]);
 }
+
+#[test]
+fn test_extract_table_generation_id() {
+    let inner_key = build_info_table_item_inner_key(TABLE_GENERATION_ITEM_ID, [0, 0, 4, 1]);
+    assert_eq!(extract_table_generation_id(&inner_key), Some([0, 0, 4, 1]));
+    let inner_key = build_info_table_item_inner_key(FREE_TABLE_ID_ITEM_ID, [0, 0, 4, 1]);
+    assert_eq!(extract_table_generation_id(&inner_key), None);
+    assert_eq!(extract_table_generation_id(&inner_key[..8]), None);
+}
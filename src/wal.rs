@@ -0,0 +1,141 @@
+use crate::Error;
+#[cfg(test)]
+use crate::{db::Db, options::Options};
+#[cfg(test)]
+use rocksdb::DBRecoveryMode;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+
+// The first sequence number of a WAL file is read from its first record, as rocksdb has no API
+// listing the WAL files with theirs. This depends on the log format of rocksdb (db/log_format.h):
+// a record starts with its checksum, length and type, followed by the number of the log it was
+// written to in recyclable logs, and a write batch starts with its sequence number.
+const WAL_RECORD_HEADER_LEN: usize = 7;
+const WAL_RECYCLABLE_RECORD_HEADER_LEN: usize = 11;
+
+// The record types a write batch can start with, whole or split across blocks.
+const WAL_FULL_RECORD_TYPE: u8 = 1;
+const WAL_FIRST_RECORD_TYPE: u8 = 2;
+const WAL_RECYCLABLE_FULL_RECORD_TYPE: u8 = 5;
+const WAL_RECYCLABLE_FIRST_RECORD_TYPE: u8 = 6;
+
+/// A WAL file, live or moved to the archive by the WAL retention options.
+pub(in crate) struct WalFile {
+    pub(in crate) number: u64,
+    pub(in crate) path: PathBuf,
+    pub(in crate) archived: bool,
+}
+
+impl WalFile {
+    /// Reads the sequence number of the first batch of the file, which leads its first record.
+    /// `None` if the file holds no batch yet or is gone.
+    pub(in crate) fn read_first_sn(&self) -> Option<u64> {
+        let mut header = Vec::with_capacity(WAL_RECYCLABLE_RECORD_HEADER_LEN + 8);
+        let file = File::open(&self.path).ok()?;
+        file.take(header.capacity() as u64).read_to_end(&mut header).ok()?;
+        let sn_offset = match *header.get(WAL_RECORD_HEADER_LEN - 1)? {
+            WAL_FULL_RECORD_TYPE | WAL_FIRST_RECORD_TYPE => WAL_RECORD_HEADER_LEN,
+            // A recycled log still holds the records of its previous use past the new ones.
+            WAL_RECYCLABLE_FULL_RECORD_TYPE | WAL_RECYCLABLE_FIRST_RECORD_TYPE
+                if header.get(WAL_RECORD_HEADER_LEN..WAL_RECYCLABLE_RECORD_HEADER_LEN)?
+                    == (self.number as u32).to_le_bytes() =>
+            {
+                WAL_RECYCLABLE_RECORD_HEADER_LEN
+            }
+            _ => return None,
+        };
+        let mut sn = [0; 8];
+        sn.copy_from_slice(header.get(sn_offset..sn_offset + 8)?);
+        Some(u64::from_le_bytes(sn))
+    }
+}
+
+/// Lists the archived and live WAL files of a database by number, which is the order they were
+/// written in.
+pub(in crate) fn list_wal_files(db_path: &Path) -> Result<Vec<WalFile>, Error> {
+    let mut files = list_wal_dir(&db_path.join("archive"), true)?;
+    files.extend(list_wal_dir(db_path, false)?);
+    files.sort_by_key(|file| file.number);
+    Ok(files)
+}
+
+/// Returns the sequence number of the first batch the WAL still holds, if any.
+pub(in crate) fn earliest_wal_sn(db_path: &Path) -> Result<Option<u64>, Error> {
+    Ok(list_wal_files(db_path)?.iter().filter_map(WalFile::read_first_sn).min())
+}
+
+fn list_wal_dir(dir: &Path, archived: bool) -> Result<Vec<WalFile>, Error> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    let mut files = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension() != Some("log".as_ref()) {
+            continue;
+        }
+        if let Some(number) = path.file_stem().and_then(|stem| stem.to_str()?.parse().ok()) {
+            files.push(WalFile { number, path, archived });
+        }
+    }
+    Ok(files)
+}
+
+#[test]
+fn test_read_first_sn() {
+    let dir = Path::new("./data/test_read_first_sn");
+    let _ = fs::remove_dir_all(dir);
+    fs::create_dir_all(dir).unwrap();
+    let write_file = |number: u64, record: &[u8]| {
+        let path = dir.join(format!("{:06}.log", number));
+        fs::write(&path, record).unwrap();
+        WalFile { number, path, archived: false }
+    };
+    let sn = 42u64.to_le_bytes();
+    let legacy = [&[0, 0, 0, 0, 12, 0, WAL_FULL_RECORD_TYPE][..], &sn, &[1, 0, 0, 0]].concat();
+    assert_eq!(write_file(7, &legacy).read_first_sn(), Some(42));
+    let recyclable = |log_number: u32| {
+        let header = [0, 0, 0, 0, 12, 0, WAL_RECYCLABLE_FIRST_RECORD_TYPE];
+        [&header[..], &log_number.to_le_bytes(), &sn, &[1, 0, 0, 0]].concat()
+    };
+    assert_eq!(write_file(8, &recyclable(8)).read_first_sn(), Some(42));
+    assert_eq!(write_file(9, &recyclable(3)).read_first_sn(), None);
+    assert_eq!(write_file(10, &[]).read_first_sn(), None);
+    assert_eq!(write_file(11, &legacy[..10]).read_first_sn(), None);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_earliest_wal_sn_with_recycled_logs() {
+    let path = "./data/test_earliest_wal_sn_with_recycled_logs";
+    let _ = Db::destroy(path);
+    let mut opts = Options::new();
+    opts.inner.set_recycle_log_file_num(1);
+    // rocksdb only recycles logs when the end of the WAL doesn't have to be told apart from junk.
+    opts.inner.set_wal_recovery_mode(DBRecoveryMode::TolerateCorruptedTailRecords);
+    let db = Db::new(path, &opts).unwrap();
+    let table = db.new_table("huobi.btc.usdt.1m").unwrap();
+    for i in 0..4u32 {
+        table.put(i.to_be_bytes(), b"v").unwrap();
+        db.flush().unwrap();
+        table.put(b"k", i.to_be_bytes()).unwrap();
+    }
+    let files = list_wal_files(Path::new(path)).unwrap();
+    let is_recyclable = |file: &WalFile| {
+        let mut header = [0; WAL_RECORD_HEADER_LEN];
+        File::open(&file.path).unwrap().read_exact(&mut header).unwrap();
+        header[WAL_RECORD_HEADER_LEN - 1] == WAL_RECYCLABLE_FULL_RECORD_TYPE
+    };
+    assert!(files.iter().all(is_recyclable));
+    let earliest_sn = earliest_wal_sn(Path::new(path)).unwrap().unwrap();
+    let batches: Vec<u64> =
+        db.get_updates_since(earliest_sn).unwrap().map(|batch| batch.unwrap().sn).collect();
+    assert_eq!(batches.last(), Some(&db.get_latest_sn()));
+    assert!(matches!(db.get_updates_since(earliest_sn - 1), Err(Error::WalGap { .. })));
+    drop(table);
+    drop(db);
+    Db::destroy(path).unwrap();
+}
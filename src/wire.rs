@@ -0,0 +1,226 @@
+#[cfg(test)]
+use crate::update::Update;
+use crate::update_batch::UpdateBatch;
+use crate::utils::*;
+use crate::Error;
+#[cfg(test)]
+use bytes::Bytes;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{ErrorKind, Read, Write};
+
+pub const WIRE_VERSION: u8 = 1;
+
+pub const FRAME_HEADER_LEN: usize = 8;
+
+/// Frames larger than this are rejected rather than allocated.
+pub const MAX_FRAME_LEN: usize = 256 << 20;
+
+/// Encodes a batch into a frame laid out as
+///
+/// ```text
+/// | len: u32 | crc32: u32 | version: u8 | payload: [u8; len - 1] |
+/// ```
+///
+/// where `len` and `crc32` are big-endian and cover the version and the payload, and the payload
+/// is the msgpack of the batch.
+#[inline]
+pub fn encode_batch(batch: &UpdateBatch) -> Result<Vec<u8>, Error> {
+    encode_frame(batch)
+}
+
+/// Encodes any value into a frame laid out like the ones of `encode_batch`.
+pub fn encode_frame<T: Serialize>(value: &T) -> Result<Vec<u8>, Error> {
+    let payload = rmp_serde::to_vec(value)
+        .map_err(|e| Error::Encode(format!("Failed to encode frame: {}", e)))?;
+    let len = payload.len() + 1;
+    if len > MAX_FRAME_LEN {
+        return Err(Error::Encode(format!("Frame is too large: {}", len)));
+    }
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + len);
+    frame.extend_from_slice(&(len as u32).to_be_bytes());
+    frame.extend_from_slice(&[0; 4]);
+    frame.push(WIRE_VERSION);
+    frame.extend_from_slice(&payload);
+    let crc = crc32fast::hash(&frame[FRAME_HEADER_LEN..]);
+    frame[4..FRAME_HEADER_LEN].copy_from_slice(&crc.to_be_bytes());
+    Ok(frame)
+}
+
+/// Decodes the frame at the start of `buf`, returning the batch and the length of the frame, or
+/// `None` if `buf` doesn't hold a whole frame yet.
+#[inline]
+pub fn decode_batch(buf: &[u8]) -> Result<Option<(UpdateBatch, usize)>, Error> {
+    decode_frame(buf)
+}
+
+/// Like `decode_batch`, for frames of any value.
+pub fn decode_frame<T: DeserializeOwned>(buf: &[u8]) -> Result<Option<(T, usize)>, Error> {
+    if buf.len() < FRAME_HEADER_LEN {
+        return Ok(None);
+    }
+    let len = check_frame_len(u8s_to_u32(&buf[..4]))?;
+    if buf.len() < FRAME_HEADER_LEN + len {
+        return Ok(None);
+    }
+    let body = &buf[FRAME_HEADER_LEN..FRAME_HEADER_LEN + len];
+    let value = decode_body(u8s_to_u32(&buf[4..FRAME_HEADER_LEN]), body)?;
+    Ok(Some((value, FRAME_HEADER_LEN + len)))
+}
+
+#[inline]
+pub fn write_batch<W: Write>(writer: &mut W, batch: &UpdateBatch) -> Result<(), Error> {
+    write_frame(writer, batch)
+}
+
+#[inline]
+pub fn write_frame<W: Write, T: Serialize>(writer: &mut W, value: &T) -> Result<(), Error> {
+    Ok(writer.write_all(&encode_frame(value)?)?)
+}
+
+/// Reads the next frame, returning `None` at the end of the stream if no frame was started.
+#[inline]
+pub fn read_batch<R: Read>(reader: &mut R) -> Result<Option<UpdateBatch>, Error> {
+    read_frame(reader)
+}
+
+/// Like `read_batch`, for frames of any value.
+pub fn read_frame<R: Read, T: DeserializeOwned>(reader: &mut R) -> Result<Option<T>, Error> {
+    let mut header = [0; FRAME_HEADER_LEN];
+    let mut read = 0;
+    while read < header.len() {
+        match reader.read(&mut header[read..]) {
+            Ok(0) if read == 0 => return Ok(None),
+            Ok(0) => return Err(Error::Io(ErrorKind::UnexpectedEof.into())),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let len = check_frame_len(u8s_to_u32(&header[..4]))?;
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    Ok(Some(decode_body(u8s_to_u32(&header[4..]), &body)?))
+}
+
+#[inline]
+fn check_frame_len(len: u32) -> Result<usize, Error> {
+    let len = len as usize;
+    if len == 0 || len > MAX_FRAME_LEN {
+        return Err(Error::corruption(format!("Invalid frame length {}", len)));
+    }
+    Ok(len)
+}
+
+fn decode_body<T: DeserializeOwned>(crc: u32, body: &[u8]) -> Result<T, Error> {
+    let actual_crc = crc32fast::hash(body);
+    if actual_crc != crc {
+        return Err(Error::corruption(format!(
+            "Frame checksum mismatch, expected {:#010x}, got {:#010x}",
+            crc, actual_crc
+        )));
+    }
+    match body[0] {
+        WIRE_VERSION => rmp_serde::from_slice(&body[1..])
+            .map_err(|e| Error::corruption(format!("Invalid frame payload: {}", e))),
+        version => Err(Error::corruption(format!("Unsupported wire version {}", version))),
+    }
+}
+
+#[cfg(test)]
+fn build_test_batch(sn: u64) -> UpdateBatch {
+    UpdateBatch {
+        sn,
+        updates: vec![
+            Update::Put { key: Bytes::from("\0\0\x04\0k1"), value: Bytes::from(vec![0, 255, 7]) },
+            Update::Delete { key: Bytes::from("\0\0\x04\0k2") },
+            Update::DeleteRange {
+                from_key: Bytes::from("\0\0\x04\0"),
+                to_key: Bytes::from("\0\0\x04\x01"),
+            },
+        ],
+    }
+}
+
+#[test]
+fn test_encode_and_decode_batch() {
+    let batch = build_test_batch(42);
+    let frame = encode_batch(&batch).unwrap();
+    assert_eq!(frame[FRAME_HEADER_LEN], WIRE_VERSION);
+    assert!(decode_batch(&frame[..frame.len() - 1]).unwrap().is_none());
+    let (decoded, len) = decode_batch(&frame).unwrap().unwrap();
+    assert_eq!(decoded, batch);
+    assert_eq!(len, frame.len());
+    let empty = UpdateBatch::new();
+    let (decoded, _) = decode_batch(&encode_batch(&empty).unwrap()).unwrap().unwrap();
+    assert_eq!(decoded, empty);
+}
+
+#[test]
+fn test_encode_batch_layout() {
+    // Peers on the same version must agree on these bytes, changes need a new `WIRE_VERSION`.
+    let expected: &[u8] = &[
+        0, 0, 0, 0x2e, 0x05, 0x5c, 0x29, 0x1a, // len, crc32
+        0x01, // version
+        0x92, 0x2a, 0x93, // [sn, [updates]]
+        0x81, 0x00, 0x92, 0xc4, 0x06, 0, 0, 4, 0, b'k', b'1', 0xc4, 0x03, 0, 0xff, 7, // Put
+        0x81, 0x01, 0x91, 0xc4, 0x06, 0, 0, 4, 0, b'k', b'2', // Delete
+        0x81, 0x02, 0x92, 0xc4, 0x04, 0, 0, 4, 0, 0xc4, 0x04, 0, 0, 4, 1, // DeleteRange
+    ];
+    assert_eq!(encode_batch(&build_test_batch(42)).unwrap(), expected);
+}
+
+#[test]
+fn test_read_and_write_batches() {
+    let mut buf = vec![];
+    for sn in 1..=3 {
+        write_batch(&mut buf, &build_test_batch(sn)).unwrap();
+    }
+    let mut reader = &buf[..];
+    for sn in 1..=3 {
+        assert_eq!(read_batch(&mut reader).unwrap().unwrap(), build_test_batch(sn));
+    }
+    assert!(read_batch(&mut reader).unwrap().is_none());
+    let mut truncated = &buf[..buf.len() - 1];
+    for _ in 1..3 {
+        read_batch(&mut truncated).unwrap();
+    }
+    assert!(matches!(read_batch(&mut truncated), Err(Error::Io(_))));
+}
+
+#[test]
+fn test_decode_invalid_frames() {
+    let frame = encode_batch(&build_test_batch(42)).unwrap();
+    let mut corrupted = frame.clone();
+    *corrupted.last_mut().unwrap() ^= 1;
+    assert!(matches!(decode_batch(&corrupted), Err(Error::Corruption(_))));
+    let mut future = frame.clone();
+    future[FRAME_HEADER_LEN] = WIRE_VERSION + 1;
+    let crc = crc32fast::hash(&future[FRAME_HEADER_LEN..]);
+    future[4..FRAME_HEADER_LEN].copy_from_slice(&crc.to_be_bytes());
+    assert!(matches!(decode_batch(&future), Err(Error::Corruption(_))));
+    assert!(matches!(decode_batch(&[255; 16]), Err(Error::Corruption(_))));
+}
+
+#[test]
+fn test_encode_batches_from_feed() {
+    run_test("test_encode_batches_from_feed", |db| {
+        let table = db.new_table("huobi.btc.usdt.1m").unwrap();
+        let sn = db.get_latest_sn();
+        table.put(b"k1", b"v1").unwrap();
+        let mut batch = table.batch();
+        batch.delete_range(b"k1", b"k2");
+        batch.put(b"k3", b"v3");
+        table.write(batch).unwrap();
+        let mut buf = vec![];
+        let batches: Vec<UpdateBatch> =
+            db.get_updates_since(sn).unwrap().map(Result::unwrap).collect();
+        for batch in &batches {
+            write_batch(&mut buf, batch).unwrap();
+        }
+        let mut reader = &buf[..];
+        for batch in &batches {
+            assert_eq!(&read_batch(&mut reader).unwrap().unwrap(), batch);
+        }
+    })
+}